@@ -1,4 +1,5 @@
 use std::io::Read;
+use std::collections::HashMap;
 
 const CHUNK_SIZE: usize = 16;
 
@@ -18,6 +19,9 @@ fn main() {
 fn run() -> Result<(), String> {
 	const BUF_MIN_LEN: usize = "Content-Length: ____\r\n\r\n".len();
 
+	// the text of the open documents by uri, requests don't send it
+	let mut documents = HashMap::new();
+
 	loop {
 		let mut buf = vec![0u8; BUF_MIN_LEN];
 		let mut n = 0;
@@ -43,15 +47,15 @@ fn run() -> Result<(), String> {
 		std::io::stdin().read_exact(&mut buf[n..]).map_err(|e| format!("{e} at {}:{}:{}", file!(), line!(), column!()))?;
 		
 		let msg = serde_json::from_slice::<serde_json::Value>(&buf[start_of_content..]).map_err(|e| format!("{e} at {}:{}:{}", file!(), line!(), column!()))?;
-		write!(
+		writeln!(
 			std::fs::File::options().create(true).append(true).open(LOG_FILE).map_err(|e| format!("{e} at {}:{}:{}", file!(), line!(), column!()))?,
-			"Recieved {msg:#}\n"
+			"Recieved {msg:#}"
 		).map_err(|e| format!("{e} at {}:{}:{}", file!(), line!(), column!()))?;
 
 		if msg["id"].is_null() {
-			process_notification(serde_json::from_value(msg).map_err(|e| format!("{e} at {}:{}:{}", file!(), line!(), column!()))?)?;
+			process_notification(serde_json::from_value(msg).map_err(|e| format!("{e} at {}:{}:{}", file!(), line!(), column!()))?, &mut documents)?;
 		} else {
-			process_request(serde_json::from_value(msg).map_err(|e| format!("{e} at {}:{}:{}", file!(), line!(), column!()))?)?;
+			process_request(serde_json::from_value(msg).map_err(|e| format!("{e} at {}:{}:{}", file!(), line!(), column!()))?, &documents)?;
 		}
 		
 
@@ -63,9 +67,9 @@ fn run() -> Result<(), String> {
 }
 
 fn send_jrpc(json: String) -> std::io::Result<()> {
-	write!(
+	writeln!(
 		std::fs::File::options().create(true).append(true).open(LOG_FILE)?,
-		"Sending {json:#}\n"
+		"Sending {json:#}"
 	)?;
 
 	write!(std::io::stdout(), "Content-Length: {len}\r\n\r\n{json}", len = json.len())?;
@@ -87,10 +91,30 @@ fn parse_headers(buf: &[u8]) -> Result<Vec<(String, String)>, String> {
 
 use lsp::*;
 use std::io::Write;
-fn process_notification(notification: LspNotification) -> Result<(), String> {
+fn process_notification(notification: LspNotification, documents: &mut HashMap<String, String>) -> Result<(), String> {
 	match notification.method.as_str() {
 		"exit" => std::process::exit(0),
 
+		"textDocument/didOpen" => {
+			let params: DidOpenTextDocumentParams = serde_json::from_value(notification.params.unwrap_or_default()).map_err(|e| format!("{e} at {}:{}:{}", file!(), line!(), column!()))?;
+			publish_diagnostics(&params.text_document.uri, &params.text_document.text).map_err(|e| format!("{e} at {}:{}:{}", file!(), line!(), column!()))?;
+			documents.insert(params.text_document.uri, params.text_document.text);
+		},
+
+		"textDocument/didChange" => {
+			let params: DidChangeTextDocumentParams = serde_json::from_value(notification.params.unwrap_or_default()).map_err(|e| format!("{e} at {}:{}:{}", file!(), line!(), column!()))?;
+			// we only ask for full syncs, so the last change has the whole document
+			if let Some(change) = params.content_changes.into_iter().last() {
+				publish_diagnostics(&params.text_document.uri, &change.text).map_err(|e| format!("{e} at {}:{}:{}", file!(), line!(), column!()))?;
+				documents.insert(params.text_document.uri, change.text);
+			}
+		},
+
+		"textDocument/didClose" => {
+			let params: DidCloseTextDocumentParams = serde_json::from_value(notification.params.unwrap_or_default()).map_err(|e| format!("{e} at {}:{}:{}", file!(), line!(), column!()))?;
+			documents.remove(&params.text_document.uri);
+		},
+
 		m => {
 			send_jrpc(serde_json::json!({
 				"jsonrpc": "2.0",
@@ -105,7 +129,7 @@ fn process_notification(notification: LspNotification) -> Result<(), String> {
 	Ok(())
 }
 
-fn process_request(request: LspRequest) -> Result<(), String> {
+fn process_request(request: LspRequest, documents: &HashMap<String, String>) -> Result<(), String> {
 	match request.method.as_str() {
 		"initialize" => {
 			send_jrpc(serde_json::to_string(
@@ -120,6 +144,8 @@ fn process_request(request: LspRequest) -> Result<(), String> {
 						}),
 
 						capabilities: InitializeResult__ServerCapabilities {
+							textDocumentSync: Some(TEXT_DOCUMENT_SYNC_KIND_FULL),
+							inlayHintProvider: Some(true),
 							..Default::default()
 						},
 					}),
@@ -136,6 +162,16 @@ fn process_request(request: LspRequest) -> Result<(), String> {
 			// }).to_string()).map_err(|e| format!("{e} at {}:{}:{}", file!(), line!(), column!()))?;
 		},
 
+		"textDocument/inlayHint" => {
+			let params: InlayHintParams = serde_json::from_value(request.params.unwrap_or_default()).map_err(|e| format!("{e} at {}:{}:{}", file!(), line!(), column!()))?;
+			let text = documents.get(&params.text_document.uri).map_or("", String::as_str);
+			send_jrpc(serde_json::json!({
+				"jsonrpc": "2.0",
+				"id": request.id,
+				"result": inlay_hints(text),
+			}).to_string()).map_err(|e| format!("{e} at {}:{}:{}", file!(), line!(), column!()))?;
+		},
+
 		"shutdown" => send_jrpc(serde_json::to_string(&LspResponse {
 			jsonrpc: "2.0".to_string(),
 			id: request.id,
@@ -166,6 +202,54 @@ fn process_request(request: LspRequest) -> Result<(), String> {
 	Ok(())
 }
 
+// https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#textDocument_publishDiagnostics
+fn publish_diagnostics(uri: &str, text: &str) -> std::io::Result<()> {
+	let diagnostics = loki::check_source(text, loki::span::FileId(0)).diagnostics;
+
+	let diagnostics = diagnostics.into_iter().map(|d| serde_json::json!({
+		"range": {
+			"start": lsp_position(text, d.span.start),
+			"end": lsp_position(text, d.span.end),
+		},
+		// Error
+		"severity": 1,
+		"source": "loki",
+		"message": d.message,
+	})).collect::<Vec<_>>();
+
+	send_jrpc(serde_json::json!({
+		"jsonrpc": "2.0",
+		"method": "textDocument/publishDiagnostics",
+		"params": {
+			"uri": uri,
+			"diagnostics": diagnostics,
+		},
+	}).to_string())
+}
+
+// https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#textDocument_inlayHint
+/// The inferred types of lets, for the whole document (the client only shows the ones in view)
+fn inlay_hints(text: &str) -> serde_json::Value {
+	let inferred = loki::check_source(text, loki::span::FileId(0)).inferred;
+
+	inferred.into_iter().map(|inferred| serde_json::json!({
+		"position": lsp_position(text, inferred.span.end),
+		"label": format!(": {}", inferred.type_),
+		// Type
+		"kind": 1,
+	})).collect()
+}
+
+/// Lsp positions are 0-based and count utf-16 code units (we don't negotiate `positionEncoding`)
+fn lsp_position(text: &str, offset: usize) -> serde_json::Value {
+	let before = &text[..offset];
+	let line = before.matches('\n').count();
+	let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+	let character = before[line_start..].encode_utf16().count();
+
+	serde_json::json!({ "line": line, "character": character })
+}
+
 mod jrpc {
 	use serde_json::Value;
 	use serde::{Deserialize, Serialize};
@@ -367,7 +451,7 @@ mod lsp {
 		/// TextDocumentSyncKind number. If omitted it defaults to
 		/// `TextDocumentSyncKind.None`.
 		// pub textDocumentSync: Option<TextDocumentSyncOptions | TextDocumentSyncKind>,
-		pub textDocumentSync: Option<()>,
+		pub textDocumentSync: Option<TextDocumentSyncKind>,
 	
 		/// Defines how notebook documents are synced.
 		///
@@ -515,7 +599,7 @@ mod lsp {
 		///
 		/// @since 3.17.0
 		// pub inlayHintProvider: Option<boolean | InlayHintOptions | InlayHintRegistrationOptions>,
-		pub inlayHintProvider: Option<()>,
+		pub inlayHintProvider: Option<bool>,
 	
 		/// The server has support for pull model diagnostics.
 		///
@@ -588,4 +672,90 @@ mod lsp {
 		/// The server's version as defined by the server.
 		pub version: Option<String>,
 	}
+
+	/// Defines how the host (editor) should sync document changes to the language server.
+	pub type TextDocumentSyncKind = i32;
+	/// Documents are synced by always sending the full content
+	/// of the document.
+	pub const TEXT_DOCUMENT_SYNC_KIND_FULL: TextDocumentSyncKind = 1;
+
+	// https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#textDocument_didOpen
+	#[derive(Deserialize, Serialize)]
+	pub struct DidOpenTextDocumentParams {
+		/// The document that was opened.
+		#[serde(rename = "textDocument")]
+		pub text_document: TextDocumentItem,
+	}
+
+	#[derive(Deserialize, Serialize)]
+	pub struct TextDocumentItem {
+		/// The text document's URI.
+		pub uri: String,
+
+		/// The text document's language identifier.
+		#[serde(rename = "languageId")]
+		pub language_id: String,
+
+		/// The version number of this document (it will increase after each
+		/// change, including undo/redo).
+		pub version: i32,
+
+		/// The content of the opened text document.
+		pub text: String,
+	}
+
+	// https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#textDocument_didChange
+	#[derive(Deserialize, Serialize)]
+	pub struct DidChangeTextDocumentParams {
+		/// The document that did change. The version number points
+		/// to the version after all provided content changes have
+		/// been applied.
+		#[serde(rename = "textDocument")]
+		pub text_document: VersionedTextDocumentIdentifier,
+
+		/// The actual content changes.
+		// only the full document form since we only ask for TEXT_DOCUMENT_SYNC_KIND_FULL
+		#[serde(rename = "contentChanges")]
+		pub content_changes: Vec<TextDocumentContentChangeEvent>,
+	}
+
+	#[derive(Deserialize, Serialize)]
+	pub struct VersionedTextDocumentIdentifier {
+		/// The text document's URI.
+		pub uri: String,
+
+		/// The version number of this document.
+		pub version: i32,
+	}
+
+	#[derive(Deserialize, Serialize)]
+	pub struct TextDocumentContentChangeEvent {
+		/// The new text of the whole document.
+		pub text: String,
+	}
+
+	// https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#textDocument_didClose
+	#[derive(Deserialize, Serialize)]
+	pub struct DidCloseTextDocumentParams {
+		/// The document that was closed.
+		#[serde(rename = "textDocument")]
+		pub text_document: TextDocumentIdentifier,
+	}
+
+	#[derive(Deserialize, Serialize)]
+	pub struct TextDocumentIdentifier {
+		/// The text document's URI.
+		pub uri: String,
+	}
+
+	// https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#inlayHintParams
+	#[derive(Deserialize, Serialize)]
+	pub struct InlayHintParams {
+		/// The text document.
+		#[serde(rename = "textDocument")]
+		pub text_document: TextDocumentIdentifier,
+
+		/// The visible document range for which inlay hints should be computed.
+		pub range: Value,
+	}
 }
@@ -0,0 +1,709 @@
+use crate::span::Span;
+use crate::symbol::Symbol;
+use crate::lexer::FloatSuffix;
+use crate::parser::{ AstRoot, ConstAssignmentVal, Member, MemberDefault, Type, Block, Statement, StatementKind, Expression, ExpressionKind, Operator };
+use crate::typeck::{ named, integer_literal_type, integer, float_rank, promote, usual_arithmetic_conversion, FLOATS };
+use std::collections::HashMap;
+
+/// What a constant evaluates to
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+	/// Already wrapped to fit in the type, which is one of C's integer types
+	Integer(i128, &'static str),
+	/// Already rounded if the type is `float`
+	Float(f64, &'static str),
+	/// From a string literal, it's a `char*`
+	String(String),
+	/// `Color.Red`, with the value of the variant
+	Enum { type_: Symbol, variant: Symbol, value: i64 },
+	/// `Shape.Circle(2.0)`, `data` is `None` for a variant without any
+	Union { type_: Symbol, variant: Symbol, data: Option<Box<Value>> },
+	/// `Point{ x = 1 }`, with the members that were given. The others get their defaults in C
+	Struct { name: Symbol, members: Vec<(Symbol, Value)> },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConstErrorKind {
+	/// The name of the constant that ended up needing its own value
+	Cycle(Symbol),
+	NotConstant,
+	NoValue,
+	DivisionByZero,
+	ShiftOutOfRange(i128),
+	NotFinite,
+	TooLong,
+	TooDeep,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConstError {
+	pub kind: ConstErrorKind,
+	pub span: Span,
+}
+
+impl Value {
+	pub fn type_(&self) -> Type {
+		match self {
+			Value::Integer(_, type_) | Value::Float(_, type_) => named(type_),
+			Value::String(_) => Type::Pointer(Box::new(named("char"))),
+			Value::Enum { type_, .. } | Value::Union { type_, .. } | Value::Struct { name: type_, .. } => Type::Named(*type_),
+		}
+	}
+
+	/// `None` if it can't be a condition in C
+	fn is_true(&self) -> Option<bool> {
+		match *self {
+			Value::Integer(n, _) => Some(n != 0),
+			Value::Float(n, _) => Some(n != 0.0),
+			// it's a pointer that isn't null
+			Value::String(_) => Some(true),
+			Value::Enum { value, .. } => Some(value != 0),
+			Value::Union { .. } | Value::Struct { .. } => None,
+		}
+	}
+}
+
+impl std::fmt::Display for Value {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Value::Integer(n, _) => write!(f, "{n}"),
+			Value::Float(n, _) => write!(f, "{n}"),
+			Value::String(s) => write!(f, "{s:?}"),
+			Value::Enum { type_, variant, .. } | Value::Union { type_, variant, data: None } => write!(f, "{type_}.{variant}"),
+			Value::Union { type_, variant, data: Some(data) } => write!(f, "{type_}.{variant}({data})"),
+			Value::Struct { name, members } => {
+				let members = members.iter().map(|(member, value)| format!("{member} = {value}")).collect::<Vec<_>>().join(", ");
+				write!(f, "{name}{{ {members} }}")
+			},
+		}
+	}
+}
+
+impl std::fmt::Display for ConstError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match &self.kind {
+			ConstErrorKind::Cycle(name) => write!(f, "'{name}' depends on itself"),
+			ConstErrorKind::NotConstant => write!(f, "this can't be evaluated at compile time"),
+			ConstErrorKind::NoValue => write!(f, "this doesn't have a value"),
+			ConstErrorKind::DivisionByZero => write!(f, "division by zero"),
+			ConstErrorKind::ShiftOutOfRange(n) => write!(f, "shifting by {n} is out of range"),
+			ConstErrorKind::NotFinite => write!(f, "the value isn't a finite number"),
+			ConstErrorKind::TooLong => write!(f, "this takes too long to evaluate, it might never finish"),
+			ConstErrorKind::TooDeep => write!(f, "too much recursion to evaluate this"),
+		}
+	}
+}
+
+/// Loop iterations and calls for one constant before giving up
+const MAX_STEPS: usize = 1_000_000;
+/// Calls inside of calls, every one of them is a few frames of the evaluator
+const MAX_DEPTH: usize = 256;
+
+/// The default values of struct members, by the name of the struct and then the member
+pub type Defaults = HashMap<(Symbol, Symbol), Value>;
+
+/// Evaluates the top level `NAME :: expr;` constants, the initial values of the globals and the defaults of struct members.
+/// Constants can use each other (in any order) and call functions, as long as the functions only work with their arguments and locals
+pub fn evaluate(ast: &AstRoot) -> (HashMap<Symbol, Value>, HashMap<Symbol, Value>, Defaults, Vec<ConstError>) {
+	let mut evaluator = Evaluator {
+		functions: HashMap::new(),
+		consts: HashMap::new(),
+		enums: HashMap::new(),
+		unions: HashMap::new(),
+		structs: HashMap::new(),
+		states: HashMap::new(),
+		locals: Vec::new(),
+		steps: 0,
+		depth: 0,
+		errors: Vec::new(),
+	};
+
+	for ca in ast.consts.iter() {
+		match &ca.val {
+			ConstAssignmentVal::Function { args, return_type, body } => { evaluator.functions.insert(ca.name, Function { args, return_type, body }); },
+			ConstAssignmentVal::Expression(expr) => { evaluator.consts.insert(ca.name, expr); },
+			ConstAssignmentVal::Enum(variants) => { evaluator.enums.insert(ca.name, variants); },
+			ConstAssignmentVal::Union(variants) => { evaluator.unions.insert(ca.name, variants); },
+			ConstAssignmentVal::Struct(members) => { evaluator.structs.insert(ca.name, members); },
+		}
+	}
+
+	for ca in ast.consts.iter() {
+		if let ConstAssignmentVal::Expression(expr) = &ca.val {
+			evaluator.steps = 0;
+			// the errors were already reported
+			let _ = evaluator.constant(ca.name, expr.span);
+		}
+	}
+
+	// other globals can change before anything reads them, so they can't be used here
+	let mut globals = HashMap::new();
+	for global in ast.globals.iter() {
+		let Some(val) = &global.val else { continue };
+		evaluator.steps = 0;
+		match evaluator.value(val).and_then(|value| convert_to(value, &global.type_, val.span)).and_then(|value| finite(value, val.span)) {
+			Ok(value) => { globals.insert(global.name, value); },
+			Err(interrupt) => evaluator.report(interrupt, val.span),
+		}
+	}
+
+	// the same, converted to the type of the member
+	let mut defaults = HashMap::new();
+	for ca in ast.consts.iter() {
+		let ConstAssignmentVal::Struct(members) = &ca.val else { continue };
+		for member in members.iter() {
+			let MemberDefault::Value(val) = &member.default else { continue };
+			evaluator.steps = 0;
+			match evaluator.value(val).and_then(|value| convert_to(value, &member.type_, val.span)).and_then(|value| finite(value, val.span)) {
+				Ok(value) => { defaults.insert((ca.name, member.name), value); },
+				Err(interrupt) => evaluator.report(interrupt, val.span),
+			}
+		}
+	}
+
+	let consts = evaluator.states.into_iter().filter_map(|(name, state)| match state {
+		State::Done(value) => Some((name, value)),
+		State::Evaluating | State::Failed => None,
+	}).collect();
+
+	(consts, globals, defaults, evaluator.errors)
+}
+
+enum State {
+	Evaluating,
+	Done(Value),
+	Failed,
+}
+
+/// Whatever stops the evaluation of an expression before it has a value
+enum Interrupt {
+	Return(Option<Value>),
+	Break(Option<Symbol>),
+	Continue(Option<Symbol>),
+	Error(ConstError),
+	/// A constant this needs had an error, it's already reported
+	Failed,
+}
+
+type EvalResult<T> = Result<T, Interrupt>;
+
+fn error<T>(kind: ConstErrorKind, span: Span) -> EvalResult<T> {
+	Err(Interrupt::Error(ConstError { kind, span }))
+}
+
+/// What calling a function needs
+#[derive(Clone, Copy)]
+struct Function<'a> {
+	args: &'a [(Symbol, Type)],
+	return_type: &'a Option<Type>,
+	body: &'a Block,
+}
+
+struct Evaluator<'a> {
+	functions: HashMap<Symbol, Function<'a>>,
+	consts: HashMap<Symbol, &'a Expression>,
+	enums: HashMap<Symbol, &'a [(Symbol, i64)]>,
+	unions: HashMap<Symbol, &'a [(Symbol, Option<Type>)]>,
+	structs: HashMap<Symbol, &'a [Member]>,
+	states: HashMap<Symbol, State>,
+	/// Of the function being evaluated, innermost last
+	locals: Vec<(Symbol, Value)>,
+	steps: usize,
+	depth: usize,
+	errors: Vec<ConstError>,
+}
+
+impl Evaluator<'_> {
+	/// `span` is where it's used, that's where a cycle gets reported
+	fn constant(&mut self, name: Symbol, span: Span) -> EvalResult<Value> {
+		match self.states.get(&name) {
+			Some(State::Done(value)) => return Ok(value.clone()),
+			Some(State::Failed) => return Err(Interrupt::Failed),
+			Some(State::Evaluating) => return error(ConstErrorKind::Cycle(name), span),
+			None => (),
+		}
+
+		let expr = self.consts[&name];
+		self.states.insert(name, State::Evaluating);
+
+		// it can't see the locals of the function that used it
+		let locals = std::mem::take(&mut self.locals);
+		let value = self.value(expr).and_then(|value| finite(value, expr.span));
+		self.locals = locals;
+
+		match value {
+			Ok(value) => {
+				self.states.insert(name, State::Done(value.clone()));
+				Ok(value)
+			},
+			Err(interrupt) => {
+				self.report(interrupt, expr.span);
+				self.states.insert(name, State::Failed);
+				Err(Interrupt::Failed)
+			},
+		}
+	}
+
+	/// Whatever stopped a constant, the initial value of a global or a default from having one
+	fn report(&mut self, interrupt: Interrupt, span: Span) {
+		match interrupt {
+			Interrupt::Error(e) => self.errors.push(e),
+			// outside of a function there's nothing to return from
+			Interrupt::Return(_) => self.errors.push(ConstError { kind: ConstErrorKind::NotConstant, span }),
+			// the parser makes sure they're in a loop
+			Interrupt::Break(_) | Interrupt::Continue(_) | Interrupt::Failed => (),
+		}
+	}
+
+	fn step(&mut self, span: Span) -> EvalResult<()> {
+		self.steps += 1;
+		if self.steps > MAX_STEPS { return error(ConstErrorKind::TooLong, span) }
+		Ok(())
+	}
+
+	/// The value of `expr` as a condition
+	fn condition(&mut self, expr: &Expression) -> EvalResult<bool> {
+		match self.value(expr)?.is_true() {
+			Some(value) => Ok(value),
+			None => error(ConstErrorKind::NotConstant, expr.span),
+		}
+	}
+
+	/// `Color.Red`, or `Shape.Circle(2.0)` when there's `data`. `None` if `expr` isn't a variant.
+	/// Typeck turns them into `ExpressionKind::Variant` later, it also reports unknown variants and missing data
+	fn variant(&mut self, expr: &Expression, data: Option<&Expression>) -> Option<EvalResult<Value>> {
+		let ExpressionKind::BinaryOperator { op: Operator::MemberAccess, left, right } = &expr.kind else { return None };
+		let (&ExpressionKind::Ident(type_), &ExpressionKind::Ident(variant)) = (&left.kind, &right.kind) else { return None };
+		// a local or a constant with the same name hides it
+		if self.locals.iter().any(|(local, _)| *local == type_) || self.consts.contains_key(&type_) { return None }
+
+		if let Some(variants) = self.enums.get(&type_) {
+			return Some(match (variants.iter().find(|(v, _)| *v == variant), data) {
+				(Some(&(_, value)), None) => Ok(Value::Enum { type_, variant, value }),
+				_ => Err(Interrupt::Failed),
+			});
+		}
+
+		let variants = self.unions.get(&type_)?;
+		Some(match (variants.iter().find(|(v, _)| *v == variant), data) {
+			(Some((_, Some(data_type))), Some(data)) => {
+				let data = self.value(data).and_then(|value| convert_to(value, data_type, data.span));
+				data.map(|data| Value::Union { type_, variant, data: Some(Box::new(data)) })
+			},
+			(Some((_, None)), None) => Ok(Value::Union { type_, variant, data: None }),
+			_ => Err(Interrupt::Failed),
+		})
+	}
+
+	/// Typeck reports unknown structs and members, and the ones that are missing
+	fn struct_literal(&mut self, name: Symbol, members: &[(Option<Symbol>, Expression)]) -> EvalResult<Value> {
+		let Some(&declared) = self.structs.get(&name) else { return Err(Interrupt::Failed) };
+
+		let mut values = Vec::with_capacity(members.len());
+		for (i, (member, val)) in members.iter().enumerate() {
+			// without names they're in order
+			let member = match member {
+				Some(member) => declared.iter().find(|declared| declared.name == *member),
+				None => declared.get(i),
+			};
+			let Some(member) = member else { return Err(Interrupt::Failed) };
+
+			let value = self.value(val)?;
+			values.push((member.name, convert_to(value, &member.type_, val.span)?));
+		}
+
+		Ok(Value::Struct { name, members: values })
+	}
+
+	/// Like `evaluate`, but it has to have a value
+	fn value(&mut self, expr: &Expression) -> EvalResult<Value> {
+		match self.evaluate(expr)? {
+			Some(value) => Ok(value),
+			None => error(ConstErrorKind::NoValue, expr.span),
+		}
+	}
+
+	fn evaluate(&mut self, expr: &Expression) -> EvalResult<Option<Value>> {
+		let value = match &expr.kind {
+			&ExpressionKind::NumberLiteral(n, suffix) => Value::Integer(n as i128, integer_literal_type(n, suffix)),
+			&ExpressionKind::FloatLiteral(n, suffix) => {
+				let type_ = match suffix {
+					None => "double",
+					Some(FloatSuffix::F) => "float",
+					Some(FloatSuffix::L) => "long double",
+				};
+				Value::Float(round(n, type_), type_)
+			},
+			ExpressionKind::StringLiteral(s) => Value::String(s.clone()),
+			// 'a' is an int in C
+			&ExpressionKind::CharLiteral(c) => Value::Integer(c as i128, "int"),
+
+			&ExpressionKind::Ident(name) => match self.locals.iter().rev().find(|(local, _)| *local == name) {
+				Some((_, value)) => value.clone(),
+				None if self.consts.contains_key(&name) => self.constant(name, expr.span)?,
+				// functions and anything else that's only there at runtime
+				None => return error(ConstErrorKind::NotConstant, expr.span),
+			},
+
+			ExpressionKind::BinaryOperator { op: op @ (Operator::LogicalAnd | Operator::LogicalOr), left, right } => {
+				// short circuits like in C
+				let left = self.condition(left)?;
+				let value = if left == matches!(op, Operator::LogicalOr) { left } else { self.condition(right)? };
+				Value::Integer(value as i128, "int")
+			},
+			// Color.Red is a member access until typeck
+			ExpressionKind::BinaryOperator { op: Operator::MemberAccess, .. } => match self.variant(expr, None) {
+				Some(value) => value?,
+				None => return error(ConstErrorKind::NotConstant, expr.span),
+			},
+			ExpressionKind::StructLiteral { name, members } => self.struct_literal(*name, members)?,
+			ExpressionKind::Variant { .. } | ExpressionKind::Switch { .. } => {
+				return error(ConstErrorKind::NotConstant, expr.span);
+			},
+			ExpressionKind::BinaryOperator { op, left, right } => match compound_operator(op) {
+				Some(op) => return self.assign(op, left, right, expr.span).map(Some),
+				None => {
+					let (left, right) = (self.value(left)?, self.value(right)?);
+					binary(op, left, right, expr.span)?
+				},
+			},
+			ExpressionKind::UnaryOperator { op, operand } => {
+				if matches!(op, Operator::Deref | Operator::AddressOf) { return error(ConstErrorKind::NotConstant, expr.span) }
+				let operand = self.value(operand)?;
+				unary(op, operand, expr.span)?
+			},
+
+			ExpressionKind::FunctionCall(callee, args) if args.len() == 1 && self.variant(callee, None).is_some() => {
+				return self.variant(callee, args.first()).unwrap().map(Some);
+			},
+			ExpressionKind::FunctionCall(callee, args) => {
+				// only calls straight to a function, a local could hide it though
+				let function = match callee.kind {
+					ExpressionKind::Ident(name) if !self.locals.iter().any(|(local, _)| *local == name) => self.functions.get(&name).copied(),
+					_ => None,
+				};
+
+				let Some(function) = function else { return error(ConstErrorKind::NotConstant, callee.span) };
+				return self.call(function, args, expr.span);
+			},
+
+			ExpressionKind::If { cond, body, else_body } => {
+				return if self.condition(cond)? {
+					self.evaluate(body)
+				} else {
+					else_body.as_ref().map_or(Ok(None), |else_body| self.evaluate(else_body))
+				};
+			},
+			ExpressionKind::Block(block) => return self.block(block),
+			ExpressionKind::Hoisted { value, .. } => return self.evaluate(value),
+
+			ExpressionKind::While { cond, body, label } => {
+				while self.condition(cond)? {
+					self.step(expr.span)?;
+					if !self.loop_body(body, *label)? { break }
+				}
+
+				return Ok(None);
+			},
+			ExpressionKind::ForIn { var, start, end, body, label, .. } => {
+				let (start, end) = (self.value(start)?, self.value(end)?);
+				let Some(type_) = usual_arithmetic_conversion(&start.type_(), &end.type_()) else { return error(ConstErrorKind::NotConstant, expr.span) };
+				let (start, end) = (convert(start, type_).unwrap(), convert(end, type_).unwrap());
+
+				let index = self.locals.len();
+				self.locals.push((*var, start));
+				let result = self.for_in(index, end, body, *label, expr.span);
+				self.locals.truncate(index);
+
+				return result.map(|_| None);
+			},
+			ExpressionKind::For { init, cond, step, body, label } => {
+				let scope = self.locals.len();
+				let result = self.for_loop(init.as_deref(), cond.as_deref(), step.as_deref(), body, *label, expr.span);
+				self.locals.truncate(scope);
+
+				return result.map(|_| None);
+			},
+		};
+
+		Ok(Some(value))
+	}
+
+	fn block(&mut self, block: &Block) -> EvalResult<Option<Value>> {
+		let scope = self.locals.len();
+		let value = self.block_in_scope(block);
+		self.locals.truncate(scope);
+		value
+	}
+
+	fn block_in_scope(&mut self, block: &Block) -> EvalResult<Option<Value>> {
+		for statement in block.statements.iter() {
+			self.statement(statement)?;
+		}
+
+		match &block.value {
+			Some(value) => self.evaluate(value),
+			None => Ok(None),
+		}
+	}
+
+	fn statement(&mut self, statement: &Statement) -> EvalResult<()> {
+		match &statement.kind {
+			StatementKind::Return(expr) => Err(Interrupt::Return(self.evaluate(expr)?)),
+			StatementKind::Let { name, type_, val, .. } => {
+				let value = match val {
+					Some(val) => self.value(val)?,
+					// it doesn't matter what it starts as, 0 is as good as anything
+					None => Value::Integer(0, "int"),
+				};
+
+				let value = match type_ {
+					Some(type_) => convert_to(value, type_, statement.span)?,
+					None => value,
+				};
+
+				self.locals.push((*name, value));
+				Ok(())
+			},
+			StatementKind::Break(label) => Err(Interrupt::Break(*label)),
+			StatementKind::Continue(label) => Err(Interrupt::Continue(*label)),
+			StatementKind::Expression(expr) => self.evaluate(expr).map(|_| ()),
+		}
+	}
+
+	/// Whether the loop keeps going
+	fn loop_body(&mut self, body: &Expression, label: Option<Symbol>) -> EvalResult<bool> {
+		// without a label it's for the innermost loop, which is this one if it got here
+		let targets = |target: Option<Symbol>| target.is_none() || target == label;
+		match self.evaluate(body) {
+			Ok(_) => Ok(true),
+			Err(Interrupt::Break(target)) if targets(target) => Ok(false),
+			Err(Interrupt::Continue(target)) if targets(target) => Ok(true),
+			Err(interrupt) => Err(interrupt),
+		}
+	}
+
+	/// The variable is at `index` in the locals
+	fn for_in(&mut self, index: usize, end: Value, body: &Expression, label: Option<Symbol>, span: Span) -> EvalResult<()> {
+		loop {
+			let var = self.locals[index].1.clone();
+			if binary(&Operator::IsLessThan, var, end.clone(), span)?.is_true() != Some(true) { return Ok(()) }
+
+			self.step(span)?;
+			if !self.loop_body(body, label)? { return Ok(()) }
+
+			// the body can change it too
+			let var = self.locals[index].1.clone();
+			let type_ = var.type_();
+			self.locals[index].1 = convert_to(binary(&Operator::Add, var, Value::Integer(1, "int"), span)?, &type_, span)?;
+		}
+	}
+
+	fn for_loop(&mut self, init: Option<&Statement>, cond: Option<&Expression>, step: Option<&Expression>, body: &Expression, label: Option<Symbol>, span: Span) -> EvalResult<()> {
+		if let Some(init) = init { self.statement(init)? }
+
+		loop {
+			if let Some(cond) = cond {
+				if !self.condition(cond)? { return Ok(()) }
+			}
+
+			self.step(span)?;
+			if !self.loop_body(body, label)? { return Ok(()) }
+
+			if let Some(step) = step { self.evaluate(step)?; }
+		}
+	}
+
+	/// `op` is the operator without the `=`, `None` for just `=`
+	fn assign(&mut self, op: Option<Operator>, left: &Expression, right: &Expression, span: Span) -> EvalResult<Value> {
+		// only the function's own locals can change
+		let ExpressionKind::Ident(name) = left.kind else { return error(ConstErrorKind::NotConstant, left.span) };
+		let Some(index) = self.locals.iter().rposition(|(local, _)| *local == name) else { return error(ConstErrorKind::NotConstant, left.span) };
+
+		let mut value = self.value(right)?;
+		let current = self.locals[index].1.clone();
+		let type_ = current.type_();
+		if let Some(op) = op {
+			value = binary(&op, current, value, span)?;
+		}
+
+		let value = convert_to(value, &type_, span)?;
+		self.locals[index].1 = value.clone();
+		Ok(value)
+	}
+
+	fn call(&mut self, function: Function, args: &[Expression], span: Span) -> EvalResult<Option<Value>> {
+		let Function { args: params, return_type, body } = function;
+		if params.len() != args.len() { return error(ConstErrorKind::NotConstant, span) }
+
+		let mut locals = Vec::with_capacity(args.len());
+		for ((param, type_), arg) in params.iter().zip(args) {
+			let value = self.value(arg)?;
+			locals.push((*param, convert_to(value, type_, arg.span)?));
+		}
+
+		self.step(span)?;
+		if self.depth == MAX_DEPTH { return error(ConstErrorKind::TooDeep, span) }
+
+		self.depth += 1;
+		let caller = std::mem::replace(&mut self.locals, locals);
+		let result = self.block(body);
+		self.locals = caller;
+		self.depth -= 1;
+
+		let value = match result {
+			Ok(value) | Err(Interrupt::Return(value)) => value,
+			Err(interrupt) => return Err(interrupt),
+		};
+
+		match (return_type, value) {
+			(Some(return_type), Some(value)) => convert_to(value, return_type, span).map(Some),
+			(Some(_), None) => error(ConstErrorKind::NoValue, span),
+			(None, _) => Ok(None),
+		}
+	}
+}
+
+/// `+=` and the rest as the operator without the `=`, `=` as `None`
+fn compound_operator(op: &Operator) -> Option<Option<Operator>> {
+	Some(match op {
+		Operator::Assign => None,
+		Operator::AddAssign => Some(Operator::Add),
+		Operator::SubtractAssign => Some(Operator::Subtract),
+		Operator::MultiplyAssign => Some(Operator::Multiply),
+		Operator::DivideAssign => Some(Operator::Divide),
+		Operator::ModuloAssign => Some(Operator::Modulo),
+		Operator::BitAndAssign => Some(Operator::BitAnd),
+		Operator::BitOrAssign => Some(Operator::BitOr),
+		Operator::BitXorAssign => Some(Operator::BitXor),
+		Operator::ShiftLeftAssign => Some(Operator::ShiftLeft),
+		Operator::ShiftRightAssign => Some(Operator::ShiftRight),
+		_ => return None,
+	})
+}
+
+fn binary(op: &Operator, left: Value, right: Value, span: Span) -> EvalResult<Value> {
+	let (left, right) = (enum_as_int(left), enum_as_int(right));
+	// the type of a shift is just the left one's
+	if let (Operator::ShiftLeft | Operator::ShiftRight, Value::Integer(left, type_), Value::Integer(right, _)) = (op, &left, &right) {
+		let type_ = promote(&named(type_)).unwrap();
+		let bits = integer(&named(type_)).unwrap().2 as i128 * 8;
+		if !(0..bits).contains(right) { return error(ConstErrorKind::ShiftOutOfRange(*right), span) }
+
+		let n = if matches!(op, Operator::ShiftLeft) { left << right } else { left >> right };
+		return Ok(Value::Integer(wrap(n, type_), type_));
+	}
+
+	let Some(type_) = usual_arithmetic_conversion(&left.type_(), &right.type_()) else { return error(ConstErrorKind::NotConstant, span) };
+	let int = |b: bool| Value::Integer(b as i128, "int");
+
+	// both are numbers, otherwise there wouldn't be a common type
+	Ok(match (convert(left, type_).unwrap(), convert(right, type_).unwrap()) {
+		(Value::Integer(left, _), Value::Integer(right, _)) => match op {
+			Operator::Divide | Operator::Modulo if right == 0 => return error(ConstErrorKind::DivisionByZero, span),
+			Operator::Add => Value::Integer(wrap(left + right, type_), type_),
+			Operator::Subtract => Value::Integer(wrap(left - right, type_), type_),
+			// the low bits are still right if it goes past i128
+			Operator::Multiply => Value::Integer(wrap(left.wrapping_mul(right), type_), type_),
+			// both round towards 0 like C
+			Operator::Divide => Value::Integer(wrap(left / right, type_), type_),
+			Operator::Modulo => Value::Integer(wrap(left % right, type_), type_),
+			Operator::BitAnd => Value::Integer(wrap(left & right, type_), type_),
+			Operator::BitOr => Value::Integer(wrap(left | right, type_), type_),
+			Operator::BitXor => Value::Integer(wrap(left ^ right, type_), type_),
+			Operator::IsEqual => int(left == right),
+			Operator::IsNotEqual => int(left != right),
+			Operator::IsLessThan => int(left < right),
+			Operator::IsGreaterThan => int(left > right),
+			Operator::IsLessOrEqual => int(left <= right),
+			Operator::IsGreaterOrEqual => int(left >= right),
+			_ => return error(ConstErrorKind::NotConstant, span),
+		},
+
+		(Value::Float(left, _), Value::Float(right, _)) => match op {
+			Operator::Add => Value::Float(round(left + right, type_), type_),
+			Operator::Subtract => Value::Float(round(left - right, type_), type_),
+			Operator::Multiply => Value::Float(round(left * right, type_), type_),
+			Operator::Divide => Value::Float(round(left / right, type_), type_),
+			Operator::IsEqual => int(left == right),
+			Operator::IsNotEqual => int(left != right),
+			Operator::IsLessThan => int(left < right),
+			Operator::IsGreaterThan => int(left > right),
+			Operator::IsLessOrEqual => int(left <= right),
+			Operator::IsGreaterOrEqual => int(left >= right),
+			// % and the bitwise ones don't work on floats in C
+			_ => return error(ConstErrorKind::NotConstant, span),
+		},
+
+		_ => unreachable!(),
+	})
+}
+
+fn unary(op: &Operator, operand: Value, span: Span) -> EvalResult<Value> {
+	if let Operator::LogicalNot = op {
+		let Some(operand) = operand.is_true() else { return error(ConstErrorKind::NotConstant, span) };
+		return Ok(Value::Integer(!operand as i128, "int"));
+	}
+
+	let operand = enum_as_int(operand);
+	let Some(type_) = promote(&operand.type_()) else { return error(ConstErrorKind::NotConstant, span) };
+	Ok(match (op, convert(operand, type_).unwrap()) {
+		(Operator::UnaryPlus, operand) => operand,
+		(Operator::Negate, Value::Integer(n, _)) => Value::Integer(wrap(-n, type_), type_),
+		(Operator::Negate, Value::Float(n, _)) => Value::Float(-n, type_),
+		(Operator::BitNot, Value::Integer(n, _)) => Value::Integer(wrap(!n, type_), type_),
+		_ => return error(ConstErrorKind::NotConstant, span),
+	})
+}
+
+/// The variants of an enum are ints in C
+fn enum_as_int(value: Value) -> Value {
+	match value {
+		Value::Enum { value, .. } => Value::Integer(value as i128, "int"),
+		value => value,
+	}
+}
+
+/// To one of C's integer or floating types, strings, variants and structs can't be converted
+fn convert(value: Value, to: &'static str) -> Option<Value> {
+	let to_float = FLOATS.contains(&to);
+	Some(match value {
+		Value::Integer(n, _) if to_float => Value::Float(round(n as f64, to), to),
+		Value::Integer(n, _) => Value::Integer(wrap(n, to), to),
+		Value::Float(n, _) if to_float => Value::Float(round(n, to), to),
+		// C doesn't say what happens when it doesn't fit, this saturates and then wraps
+		Value::Float(n, _) => Value::Integer(wrap(n as i128, to), to),
+		Value::String(_) | Value::Enum { .. } | Value::Union { .. } | Value::Struct { .. } => return None,
+	})
+}
+
+/// For lets, arguments and return values
+fn convert_to(value: Value, type_: &Type, span: Span) -> EvalResult<Value> {
+	if let Some(to) = integer(type_).map(|(name, ..)| name).or_else(|| float_rank(type_).map(|rank| FLOATS[rank])) {
+		if let Some(value) = convert(value, to) { return Ok(value) }
+	} else if value.type_() == *type_ {
+		return Ok(value);
+	}
+
+	error(ConstErrorKind::NotConstant, span)
+}
+
+/// C doesn't have a way to write down inf or nan, not without math.h
+fn finite(value: Value, span: Span) -> EvalResult<Value> {
+	match value {
+		Value::Float(n, _) if !n.is_finite() => error(ConstErrorKind::NotFinite, span),
+		value => Ok(value),
+	}
+}
+
+/// Into the range of the integer type `to`, like C's unsigned arithmetic (and signed, on every machine)
+fn wrap(n: i128, to: &str) -> i128 {
+	let (_, _, size, signed) = integer(&named(to)).unwrap();
+	let bits = size as u32 * 8;
+	let n = n & ((1 << bits) - 1);
+	if signed && n >= 1 << (bits - 1) { n - (1 << bits) } else { n }
+}
+
+fn round(n: f64, to: &str) -> f64 {
+	if to == "float" { n as f32 as f64 } else { n }
+}
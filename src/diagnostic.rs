@@ -0,0 +1,36 @@
+use crate::span::Span;
+use crate::lexer::LexError;
+use crate::parser::ParseError;
+use crate::typeck::TypeError;
+use crate::consteval::ConstError;
+
+/// What both the cli and the lsp report, every stage's error converts into this
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+	pub span: Span,
+	pub message: String,
+}
+
+impl From<LexError> for Diagnostic {
+	fn from(error: LexError) -> Self {
+		Diagnostic { span: error.span, message: error.to_string() }
+	}
+}
+
+impl From<ParseError> for Diagnostic {
+	fn from(error: ParseError) -> Self {
+		Diagnostic { span: error.span, message: error.to_string() }
+	}
+}
+
+impl From<TypeError> for Diagnostic {
+	fn from(error: TypeError) -> Self {
+		Diagnostic { span: error.span, message: error.to_string() }
+	}
+}
+
+impl From<ConstError> for Diagnostic {
+	fn from(error: ConstError) -> Self {
+		Diagnostic { span: error.span, message: error.to_string() }
+	}
+}
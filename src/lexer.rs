@@ -1,139 +1,878 @@
-// FIXME: instead of String use like a unique string table (FlyString? in serenity)
-#[derive(Debug, PartialEq, Eq, Clone)]
+use crate::span::{ Span, FileId };
+use crate::symbol::Symbol;
+
+#[derive(Debug, PartialEq, Clone)]
 pub enum Token {
 	Fn,
 	Return,
 	Let,
 	If,
+	Else,
 	While,
+	For,
+	In,
+	Break,
+	Continue,
 	Struct,
+	Enum,
+	Union,
+	Switch,
 
 	// FIXME: maybe it should be 2 Colon tokens
 	// https://odin-lang.org/docs/faq/#what-does--mean-1
 	ColonColon,
 	Arrow,
+	/// `=>`, between a switch arm's variants and its body
+	FatArrow,
 	Colon,
 	Semicolon,
 	Comma,
 	Period,
+	PeriodPeriod,
+	/// `---`, on purpose uninitialized
+	HyphenHyphenHyphen,
 
 	Plus,
 	Hyphen,
 	Star, // FIXME: maybe asterisk?
+	Slash,
+	Percent,
 	Equals,
 	EqualsEquals,
+	Exclamation,
+	ExclamationEquals,
+	Tilde,
+	Ampersand,
+	AmpersandAmpersand,
+	Pipe,
+	PipePipe,
+	Caret,
 
 	ParenOpen,
 	ParenClose,
 	BraceOpen,
 	BraceClose,
+	BracketOpen,
+	BracketClose,
 	AngleBracketOpen,
 	AngleBracketClose,
+	AngleBracketOpenEquals,
+	AngleBracketCloseEquals,
+	AngleBracketOpenOpen,
+	AngleBracketCloseClose,
+
+	PlusEquals,
+	HyphenEquals,
+	StarEquals,
+	SlashEquals,
+	PercentEquals,
+	AmpersandEquals,
+	PipeEquals,
+	CaretEquals,
+	AngleBracketOpenOpenEquals,
+	AngleBracketCloseCloseEquals,
 
-	Ident(String),
-	NumberLiteral(i64),
+	Ident(Symbol),
+	NumberLiteral(u64, Option<IntSuffix>),
+	FloatLiteral(f64, Option<FloatSuffix>),
 	StringLiteral(String),
+	CharLiteral(char),
+
+	/// The text after `///`, the parser attaches it to the next const assignment
+	DocComment(String),
+
+	Eof,
+}
 
-	#[allow(non_camel_case_types, dead_code)]
-	FIXME_DELETE(std::convert::Infallible),
+/// Same as C's, case insensitive
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum IntSuffix {
+	U,
+	L,
+	UL,
+	LL,
+	ULL,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FloatSuffix {
+	/// float
+	F,
+	/// long double
+	L,
 }
 
 impl Token {
-	pub fn ident(&self) -> Option<String> {
+	pub fn ident(&self) -> Option<Symbol> {
 		match self {
-			Token::Ident(ident) => Some(ident.clone()),
+			Token::Ident(ident) => Some(*ident),
 			_ => None,
 		}
 	}
 }
 
-pub fn lex(input: &str) -> Vec<Token> {
-	let mut pos = 0;
-	let mut tokens = Vec::new();
-	// FIXME: maybe it can be an iterator
-	let input: Vec<char> = input.chars().collect();
+impl std::fmt::Display for Token {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let s = match self {
+			Token::Fn => "'fn'",
+			Token::Return => "'return'",
+			Token::Let => "'let'",
+			Token::If => "'if'",
+			Token::Else => "'else'",
+			Token::While => "'while'",
+			Token::For => "'for'",
+			Token::In => "'in'",
+			Token::Break => "'break'",
+			Token::Continue => "'continue'",
+			Token::Struct => "'struct'",
+			Token::Enum => "'enum'",
+			Token::Union => "'union'",
+			Token::Switch => "'switch'",
+
+			Token::ColonColon => "'::'",
+			Token::Arrow => "'->'",
+			Token::FatArrow => "'=>'",
+			Token::Colon => "':'",
+			Token::Semicolon => "';'",
+			Token::Comma => "','",
+			Token::Period => "'.'",
+			Token::PeriodPeriod => "'..'",
+
+			Token::Plus => "'+'",
+			Token::Hyphen => "'-'",
+			Token::HyphenHyphenHyphen => "'---'",
+			Token::Star => "'*'",
+			Token::Slash => "'/'",
+			Token::Percent => "'%'",
+			Token::Equals => "'='",
+			Token::EqualsEquals => "'=='",
+			Token::Exclamation => "'!'",
+			Token::ExclamationEquals => "'!='",
+			Token::Tilde => "'~'",
+			Token::Ampersand => "'&'",
+			Token::AmpersandAmpersand => "'&&'",
+			Token::Pipe => "'|'",
+			Token::PipePipe => "'||'",
+			Token::Caret => "'^'",
+
+			Token::ParenOpen => "'('",
+			Token::ParenClose => "')'",
+			Token::BraceOpen => "'{'",
+			Token::BraceClose => "'}'",
+			Token::BracketOpen => "'['",
+			Token::BracketClose => "']'",
+			Token::AngleBracketOpen => "'<'",
+			Token::AngleBracketClose => "'>'",
+			Token::AngleBracketOpenEquals => "'<='",
+			Token::AngleBracketCloseEquals => "'>='",
+			Token::AngleBracketOpenOpen => "'<<'",
+			Token::AngleBracketCloseClose => "'>>'",
+
+			Token::PlusEquals => "'+='",
+			Token::HyphenEquals => "'-='",
+			Token::StarEquals => "'*='",
+			Token::SlashEquals => "'/='",
+			Token::PercentEquals => "'%='",
+			Token::AmpersandEquals => "'&='",
+			Token::PipeEquals => "'|='",
+			Token::CaretEquals => "'^='",
+			Token::AngleBracketOpenOpenEquals => "'<<='",
+			Token::AngleBracketCloseCloseEquals => "'>>='",
+
+			Token::Ident(ident) => return write!(f, "identifier '{ident}'"),
+			Token::NumberLiteral(n, _) => return write!(f, "number {n}"),
+			Token::FloatLiteral(n, _) => return write!(f, "number {n:?}"),
+			Token::StringLiteral(s) => return write!(f, "string {s:?}"),
+			Token::CharLiteral(c) => return write!(f, "character {c:?}"),
+
+			Token::DocComment(_) => "doc comment",
+
+			Token::Eof => "end of file",
+		};
+
+		f.write_str(s)
+	}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpannedToken {
+	pub token: Token,
+	pub span: Span,
+}
+
+/// What `lex` drops, `lex_lossless` keeps it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriviaKind {
+	/// Spaces, tabs and \r that isn't part of a \r\n
+	Whitespace,
+	/// \n or \r\n
+	Newline,
+	LineComment,
+	BlockComment,
+	/// Input that was skipped because of an error
+	Skipped,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trivia {
+	pub kind: TriviaKind,
+	pub span: Span,
+}
+
+/// The trivia after a token up to the end of its line is trailing, the rest belongs to the next token.
+/// So a comment at the end of a line stays with what's on that line
+#[derive(Debug, Clone, PartialEq)]
+pub struct LosslessToken {
+	pub leading: Vec<Trivia>,
+	pub token: SpannedToken,
+	pub trailing: Vec<Trivia>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LexErrorKind {
+	UnexpectedCharacter(char),
+	UnterminatedString,
+	UnterminatedChar,
+	UnterminatedComment,
+	EmptyChar,
+	CharTooLong,
+	UnknownEscape(char),
+	InvalidHexEscape,
+	InvalidUnicodeEscape,
+	NumberTooLarge,
+	FloatOutOfRange,
+	MissingDigits,
+	MissingExponentDigits,
+	InvalidDigit(char, u32),
+	InvalidSuffix(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LexError {
+	pub kind: LexErrorKind,
+	pub span: Span,
+}
+
+impl std::fmt::Display for LexError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match &self.kind {
+			LexErrorKind::UnexpectedCharacter(c) => write!(f, "unexpected character {c:?}"),
+			LexErrorKind::UnterminatedString => write!(f, "unterminated string literal"),
+			LexErrorKind::UnterminatedChar => write!(f, "unterminated character literal"),
+			LexErrorKind::UnterminatedComment => write!(f, "unterminated block comment"),
+			LexErrorKind::EmptyChar => write!(f, "empty character literal"),
+			LexErrorKind::CharTooLong => write!(f, "character literal can only have one character, use \" for strings"),
+			LexErrorKind::UnknownEscape(c) => write!(f, "unknown escape sequence '\\{c}'"),
+			LexErrorKind::InvalidHexEscape => write!(f, "invalid '\\x' escape, expected 2 hex digits up to 7F"),
+			LexErrorKind::InvalidUnicodeEscape => write!(f, "invalid '\\u' escape, expected a unicode scalar value as 1 to 6 hex digits in braces"),
+			LexErrorKind::NumberTooLarge => write!(f, "number literal is too large"),
+			LexErrorKind::FloatOutOfRange => write!(f, "float literal is out of range"),
+			LexErrorKind::MissingDigits => write!(f, "expected digits after the base prefix"),
+			LexErrorKind::MissingExponentDigits => write!(f, "expected digits in the exponent"),
+			LexErrorKind::InvalidDigit(c, radix) => write!(f, "invalid digit {c:?} in base {radix} number literal"),
+			LexErrorKind::InvalidSuffix(suffix) => write!(f, "invalid number literal suffix {suffix:?}"),
+		}
+	}
+}
+
+/// Lexes the whole file, on errors it skips the offending input and keeps going so every error gets reported.
+/// The last token is always `Token::Eof`
+pub fn lex(input: &str, file: FileId) -> (Vec<SpannedToken>, Vec<LexError>) {
+	let mut lexer = Lexer::new(input, file);
+	let tokens = lexer.by_ref().collect();
+	return (tokens, lexer.errors);
+}
+
+/// Like `lex` but keeps the whitespace and comments as trivia on the tokens, for formatting and refactoring.
+/// Every byte of `input` is in exactly one token or trivia, so `source_text` gives back the same input
+pub fn lex_lossless(input: &str, file: FileId) -> (Vec<LosslessToken>, Vec<LexError>) {
+	let mut lexer = Lexer::new(input, file);
+	let mut tokens: Vec<LosslessToken> = Vec::new();
+	let mut leading = Vec::new();
+	let mut in_trailing = false;
+
+	while let Some((lexed, span)) = lexer.next_lexed() {
+		match lexed {
+			Lexed::Token(token) => {
+				tokens.push(LosslessToken { leading: std::mem::take(&mut leading), token: SpannedToken { token, span }, trailing: Vec::new() });
+				in_trailing = true;
+			},
+
+			Lexed::Trivia(kind) => {
+				let trivia = Trivia { kind, span };
+				match tokens.last_mut() {
+					Some(last) if in_trailing => {
+						last.trailing.push(trivia);
+						in_trailing = kind != TriviaKind::Newline;
+					},
+					_ => leading.push(trivia),
+				}
+			},
+		}
+	}
+
+	return (tokens, lexer.errors);
+}
+
+/// Puts the tokens from `lex_lossless` back together
+pub fn source_text(input: &str, tokens: &[LosslessToken]) -> String {
+	let mut text = String::with_capacity(input.len());
+	for token in tokens {
+		let spans = token.leading.iter().map(|t| t.span)
+			.chain(std::iter::once(token.token.span))
+			.chain(token.trailing.iter().map(|t| t.span));
+		for span in spans { text.push_str(&input[span.start..span.end]) }
+	}
+
+	text
+}
+
+/// Lexes straight from the `&str` one token at a time, the last token is `Token::Eof` and then it stops.
+/// Errors are pushed to `errors` as the tokens that had them are produced
+pub struct Lexer<'a> {
+	input: &'a str,
+	file: FileId,
+	pos: usize,
+	line: u32,
+	col: u32,
+	done: bool,
+	pub errors: Vec<LexError>,
+}
+
+enum Lexed {
+	Token(Token),
+	Trivia(TriviaKind),
+}
+
+/// Where a token (or error) started
+#[derive(Clone, Copy)]
+struct Mark {
+	pos: usize,
+	line: u32,
+	col: u32,
+}
+
+impl<'a> Lexer<'a> {
+	pub fn new(input: &'a str, file: FileId) -> Self {
+		Self::new_at(input, file, 0, 1, 1)
+	}
+
+	/// Starts at the byte `offset`, which is at `line` and `col`. Spans are still offsets into the whole `input`,
+	/// so part of a file can be lexed again without starting from the top
+	pub fn new_at(input: &'a str, file: FileId, offset: usize, line: u32, col: u32) -> Self {
+		Self { input, file, pos: offset, line, col, done: false, errors: Vec::new() }
+	}
 
-	while pos < input.len() {
-		match input[pos] {
-			' ' | '\t' | '\n' => pos += 1,
-			'/' if input[pos + 1] == '/' => {
-				while pos < input.len() && input[pos] != '\n' { pos += 1 }
-				pos += 1;
+	/// Lexes one token starting at `c`, trivia when it was whitespace, a comment or input skipped because of an error
+	fn lex_token(&mut self, c: char, start: Mark) -> Lexed {
+		let token = match c {
+			'\n' => { self.bump(); return Lexed::Trivia(TriviaKind::Newline) },
+			'\r' if self.peek(1, b'\n') => { self.skip(1); self.bump(); return Lexed::Trivia(TriviaKind::Newline) },
+			' ' | '\t' | '\r' => {
+				while self.peek(0, b' ') || self.peek(0, b'\t') || self.peek(0, b'\r') && !self.peek(1, b'\n') { self.skip(1) }
+				return Lexed::Trivia(TriviaKind::Whitespace);
+			},
+			// but //// is just a comment
+			'/' if self.peek(1, b'/') && self.peek(2, b'/') && !self.peek(3, b'/') => {
+				self.skip(3);
+				if self.peek(0, b' ') { self.skip(1) }
+				let text_start = self.pos;
+				self.skip_line();
+				Token::DocComment(self.input[text_start..self.pos].to_string())
 			},
 
-			':' if input[pos + 1] == ':' => {
-				tokens.push(Token::ColonColon);
-				pos += 2;
+			'/' if self.peek(1, b'/') => {
+				self.skip_line();
+				return Lexed::Trivia(TriviaKind::LineComment);
 			},
 
-			'-' if input[pos + 1] == '>' => {
-				tokens.push(Token::Arrow);
-				pos += 2;
+			// /* they /* nest */ */
+			'/' if self.peek(1, b'*') => {
+				self.skip(2);
+				let mut depth = 1;
+				while depth > 0 && self.pos < self.input.len() {
+					if self.peek(0, b'/') && self.peek(1, b'*') {
+						depth += 1;
+						self.skip(2);
+					} else if self.peek(0, b'*') && self.peek(1, b'/') {
+						depth -= 1;
+						self.skip(2);
+					} else {
+						self.bump();
+					}
+				}
+
+				if depth > 0 {
+					self.error(LexErrorKind::UnterminatedComment, start);
+				}
+				return Lexed::Trivia(TriviaKind::BlockComment);
 			},
 
-			':' => { tokens.push(Token::Colon); pos += 1 },
-			';' => { tokens.push(Token::Semicolon); pos += 1 },
-			',' => { tokens.push(Token::Comma); pos += 1 },
-			'.' => { tokens.push(Token::Period); pos += 1 },
+			':' if self.peek(1, b':') => { self.skip(2); Token::ColonColon },
+			'-' if self.peek(1, b'>') => { self.skip(2); Token::Arrow },
+			'-' if self.peek(1, b'-') && self.peek(2, b'-') => { self.skip(3); Token::HyphenHyphenHyphen },
+
+			':' => { self.skip(1); Token::Colon },
+			';' => { self.skip(1); Token::Semicolon },
+			',' => { self.skip(1); Token::Comma },
+			'.' if self.peek(1, b'.') => { self.skip(2); Token::PeriodPeriod },
+			'.' => { self.skip(1); Token::Period },
 			
-			'+' => { tokens.push(Token::Plus); pos += 1 },
-			'-' => { tokens.push(Token::Hyphen); pos += 1 },
-			'*' => { tokens.push(Token::Star); pos += 1 },
+			'+' if self.peek(1, b'=') => { self.skip(2); Token::PlusEquals },
+			'+' => { self.skip(1); Token::Plus },
+			'-' if self.peek(1, b'=') => { self.skip(2); Token::HyphenEquals },
+			'-' => { self.skip(1); Token::Hyphen },
+			'*' if self.peek(1, b'=') => { self.skip(2); Token::StarEquals },
+			'*' => { self.skip(1); Token::Star },
+			'/' if self.peek(1, b'=') => { self.skip(2); Token::SlashEquals },
+			'/' => { self.skip(1); Token::Slash },
+			'%' if self.peek(1, b'=') => { self.skip(2); Token::PercentEquals },
+			'%' => { self.skip(1); Token::Percent },
 
-			'=' if input[pos + 1] == '=' => { tokens.push(Token::EqualsEquals); pos += 2 },
-			'=' => { tokens.push(Token::Equals); pos += 1 },
+			'=' if self.peek(1, b'=') => { self.skip(2); Token::EqualsEquals },
+			'=' if self.peek(1, b'>') => { self.skip(2); Token::FatArrow },
+			'=' => { self.skip(1); Token::Equals },
+			'!' if self.peek(1, b'=') => { self.skip(2); Token::ExclamationEquals },
+			'!' => { self.skip(1); Token::Exclamation },
+			'~' => { self.skip(1); Token::Tilde },
+			'&' if self.peek(1, b'&') => { self.skip(2); Token::AmpersandAmpersand },
+			'&' if self.peek(1, b'=') => { self.skip(2); Token::AmpersandEquals },
+			'&' => { self.skip(1); Token::Ampersand },
+			'|' if self.peek(1, b'|') => { self.skip(2); Token::PipePipe },
+			'|' if self.peek(1, b'=') => { self.skip(2); Token::PipeEquals },
+			'|' => { self.skip(1); Token::Pipe },
+			'^' if self.peek(1, b'=') => { self.skip(2); Token::CaretEquals },
+			'^' => { self.skip(1); Token::Caret },
 
-			'(' => { tokens.push(Token::ParenOpen); pos += 1 },
-			')' => { tokens.push(Token::ParenClose); pos += 1 },
-			'{' => { tokens.push(Token::BraceOpen); pos += 1 },
-			'}' => { tokens.push(Token::BraceClose); pos += 1 },
-			'<' => { tokens.push(Token::AngleBracketOpen); pos += 1 },
-			'>' => { tokens.push(Token::AngleBracketClose); pos += 1 },
+			'(' => { self.skip(1); Token::ParenOpen },
+			')' => { self.skip(1); Token::ParenClose },
+			'{' => { self.skip(1); Token::BraceOpen },
+			'}' => { self.skip(1); Token::BraceClose },
+			'[' => { self.skip(1); Token::BracketOpen },
+			']' => { self.skip(1); Token::BracketClose },
+			'<' if self.peek(1, b'<') && self.peek(2, b'=') => { self.skip(3); Token::AngleBracketOpenOpenEquals },
+			'<' if self.peek(1, b'<') => { self.skip(2); Token::AngleBracketOpenOpen },
+			'<' if self.peek(1, b'=') => { self.skip(2); Token::AngleBracketOpenEquals },
+			'<' => { self.skip(1); Token::AngleBracketOpen },
+			'>' if self.peek(1, b'>') && self.peek(2, b'=') => { self.skip(3); Token::AngleBracketCloseCloseEquals },
+			'>' if self.peek(1, b'>') => { self.skip(2); Token::AngleBracketCloseClose },
+			'>' if self.peek(1, b'=') => { self.skip(2); Token::AngleBracketCloseEquals },
+			'>' => { self.skip(1); Token::AngleBracketClose },
 
-			// TODO: handle things like \n, \"
 			'"' => {
-				pos += 1;
-				let start = pos;
-				while pos < input.len() && input[pos] != '"' { pos += 1 }
-				assert_eq!(input[pos], '"');
-				let str = String::from_iter(&input[start..pos]);
-				pos += 1;
-				tokens.push(Token::StringLiteral(str));
+				self.skip(1);
+				let mut str = String::new();
+				loop {
+					match self.current() {
+						Some('"') => { self.skip(1); break },
+						Some('\\') => if let Some(c) = self.lex_escape() { str.push(c) },
+						Some(c) => { self.bump(); str.push(c) },
+						None => {
+							self.error(LexErrorKind::UnterminatedString, start);
+							break;
+						},
+					}
+				}
+
+				Token::StringLiteral(str)
+			},
+
+			'\'' => {
+				self.skip(1);
+				let c = match self.current() {
+					Some('\\') => self.lex_escape(),
+					Some('\'') => {
+						self.skip(1);
+						self.error(LexErrorKind::EmptyChar, start);
+						return Lexed::Token(Token::CharLiteral('\0'));
+					},
+					Some(c) => { self.bump(); Some(c) },
+					None => None,
+				};
+
+				if self.peek(0, b'\'') {
+					self.skip(1);
+				} else {
+					// 'abc' is probably meant to be a string, skip all of it. otherwise it's just missing the '
+					let rest = &self.input[self.pos..];
+					match rest.find(['\'', '\n', ';']) {
+						Some(end) if rest.as_bytes()[end] == b'\'' => {
+							let end = self.pos + end + 1;
+							while self.pos < end { self.bump(); }
+							self.error(LexErrorKind::CharTooLong, start);
+						},
+						_ => self.error(LexErrorKind::UnterminatedChar, start),
+					}
+				}
+
+				// the error was already reported, still give the parser something
+				Token::CharLiteral(c.unwrap_or('\0'))
+			},
+
+			// r"raw", r#"can have " in it"#, as many #s as needed
+			'r' if self.peek(1, b'"') || self.peek(1, b'#') => {
+				self.skip(1);
+				let hashes_start = self.mark();
+				let mut hashes = 0;
+				while self.peek(0, b'#') { hashes += 1; self.skip(1) }
+
+				if !self.peek(0, b'"') {
+					self.error(LexErrorKind::UnexpectedCharacter('#'), hashes_start);
+					return Lexed::Trivia(TriviaKind::Skipped);
+				}
+
+				self.skip(1);
+				let content_start = self.pos;
+				let terminated = loop {
+					if self.pos >= self.input.len() { break false }
+					if self.peek(0, b'"') && (1..=hashes).all(|i| self.peek(i, b'#')) { break true }
+					self.bump();
+				};
+
+				let str = self.input[content_start..self.pos].to_string();
+				if terminated {
+					self.skip(1 + hashes);
+				} else {
+					self.error(LexErrorKind::UnterminatedString, start);
+				}
+
+				Token::StringLiteral(str)
 			},
 
 			c if is_ident_start(c) => {
-				let start = pos;
-				pos += 1;
-				while pos < input.len() && is_ident_anywhere(input[pos]) { pos += 1 }
-				let ident = String::from_iter(&input[start..pos]);
-				tokens.push(match ident.as_str() {
+				self.skip_while(is_ident_anywhere);
+				match &self.input[start.pos..self.pos] {
 					"fn" => Token::Fn,
 					"return" => Token::Return,
 					"let" => Token::Let,
 					"if" => Token::If,
+					"else" => Token::Else,
 					"while" => Token::While,
+					"for" => Token::For,
+					"in" => Token::In,
+					"break" => Token::Break,
+					"continue" => Token::Continue,
 					"struct" => Token::Struct,
-					_ => Token::Ident(ident),
-				});
+					"enum" => Token::Enum,
+					"union" => Token::Union,
+					"switch" => Token::Switch,
+					ident => Token::Ident(Symbol::intern(ident)),
+				}
 			},
 
-			// FIXME: handle different bases (and also like U / L suffixes)
-			'0'..='9' => {
-				let start = pos;
-				pos += 1;
-				while pos < input.len() && matches!(input[pos], '0'..='9') { pos += 1 }
-				tokens.push(Token::NumberLiteral(String::from_iter(&input[start..pos]).parse().unwrap()));
+			'0'..='9' => self.lex_number(start),
+
+			c => {
+				self.bump();
+				self.error(LexErrorKind::UnexpectedCharacter(c), start);
+				return Lexed::Trivia(TriviaKind::Skipped);
 			},
+		};
+
+		Lexed::Token(token)
+	}
+
+	/// 0x1F, 0o17, 0b1010, 1_000_000, 10u, 10ull, 1.5, 1e-3, 2.5f
+	fn lex_number(&mut self, start: Mark) -> Token {
+		let input = self.input;
+		let radix = match (input.as_bytes()[start.pos], input.as_bytes().get(start.pos + 1)) {
+			(b'0', Some(b'x' | b'X')) => 16,
+			(b'0', Some(b'o' | b'O')) => 8,
+			(b'0', Some(b'b' | b'B')) => 2,
+			_ => 10,
+		};
 
-			c => unimplemented!("Unexpected character: {c}"),
+		if radix != 10 { self.skip(2) }
+		let digits_start = self.pos;
+		self.skip_while(|c| c.is_digit(radix) || c == '_');
+
+		let mut is_float = false;
+		if radix == 10 {
+			// a digit has to follow the . so 1..2 and 1.foo aren't floats
+			if self.peek(0, b'.') && self.peek_digit(1) {
+				is_float = true;
+				self.skip(1);
+				self.skip_while(|c| c.is_ascii_digit() || c == '_');
+			}
+
+			let sign = if self.peek(1, b'+') || self.peek(1, b'-') { 1 } else { 0 };
+			if (self.peek(0, b'e') || self.peek(0, b'E')) && self.peek_digit(1 + sign) {
+				is_float = true;
+				self.skip(1 + sign);
+				self.skip_while(|c| c.is_ascii_digit() || c == '_');
+			}
+		}
+
+		let digits_end = self.pos;
+		// the suffix, and anything else that got stuck to the number (like the 2 in 0b102)
+		self.skip_while(is_ident_anywhere);
+
+		let digits = input[digits_start..digits_end].replace('_', "");
+		let suffix = &input[digits_end..self.pos];
+
+		if digits.is_empty() {
+			self.error(LexErrorKind::MissingDigits, start);
+			return Token::NumberLiteral(0, None);
+		}
+
+		if let Some(c) = suffix.chars().next().filter(char::is_ascii_digit) {
+			self.error(LexErrorKind::InvalidDigit(c, radix), start);
+			return Token::NumberLiteral(0, None);
 		}
+
+		if is_float {
+			let suffix = match suffix.to_ascii_lowercase().as_str() {
+				"" => None,
+				"f" => Some(FloatSuffix::F),
+				"l" => Some(FloatSuffix::L),
+				_ => {
+					self.error(LexErrorKind::InvalidSuffix(suffix.to_string()), start);
+					None
+				},
+			};
+
+			// digits is only ascii digits, '.', 'e' and a sign here so it always parses
+			let value: f64 = digits.parse().unwrap();
+			if value.is_infinite() {
+				self.error(LexErrorKind::FloatOutOfRange, start);
+			}
+
+			return Token::FloatLiteral(value, suffix);
+		}
+
+		let suffix = match suffix.to_ascii_lowercase().as_str() {
+			"" => None,
+			"u" => Some(IntSuffix::U),
+			"l" => Some(IntSuffix::L),
+			"ul" | "lu" => Some(IntSuffix::UL),
+			"ll" => Some(IntSuffix::LL),
+			"ull" | "llu" => Some(IntSuffix::ULL),
+			_ if radix == 10 && suffix.starts_with(['e', 'E']) => {
+				// 1e without digits after it
+				self.error(LexErrorKind::MissingExponentDigits, start);
+				None
+			},
+			_ => {
+				self.error(LexErrorKind::InvalidSuffix(suffix.to_string()), start);
+				None
+			},
+		};
+
+		let unsigned = matches!(suffix, Some(IntSuffix::U | IntSuffix::UL | IntSuffix::ULL));
+		// like in C hex, octal and binary literals can go up to the unsigned max
+		let max = if unsigned || radix != 10 { u64::MAX } else { i64::MAX as u64 };
+		match u64::from_str_radix(&digits, radix).ok().filter(|&n| n <= max) {
+			Some(n) => Token::NumberLiteral(n, suffix),
+			None => {
+				self.error(LexErrorKind::NumberTooLarge, start);
+				Token::NumberLiteral(0, suffix)
+			},
+		}
+	}
+
+	/// Starts at the backslash and ends up after the escape, invalid escapes are reported and give `None`
+	fn lex_escape(&mut self) -> Option<char> {
+		let start = self.mark();
+		self.skip(1);
+		// at the end of the input the string is unterminated, that gets reported by the caller
+		let c = self.bump()?;
+
+		let rest = &self.input[self.pos..];
+		let hex_digits = |digits: &str| -> Option<u32> {
+			if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_hexdigit()) { return None }
+			u32::from_str_radix(digits, 16).ok()
+		};
+		// what a bad escape was supposed to be is skipped too, it shouldn't look like more characters after it
+		let body_len = |rest: &str, max: usize| rest.bytes().take(max).take_while(u8::is_ascii_alphanumeric).count();
+
+		let escaped = match c {
+			'n' => '\n',
+			't' => '\t',
+			'r' => '\r',
+			'0' => '\0',
+			'\\' => '\\',
+			'"' => '"',
+			'\'' => '\'',
+
+			// \x7F
+			'x' => {
+				let Some(c) = rest.get(..2).and_then(hex_digits).filter(|&value| value <= 0x7F).and_then(char::from_u32) else {
+					self.error(LexErrorKind::InvalidHexEscape, start);
+					self.skip(body_len(rest, 2));
+					return None;
+				};
+
+				self.skip(2);
+				c
+			},
+
+			// \u{1F600}
+			'u' => {
+				let digits = rest.strip_prefix('{').and_then(|rest| rest.split_once('}')).map_or("", |(digits, _)| digits);
+				let Some(c) = hex_digits(digits).filter(|_| digits.len() <= 6).and_then(char::from_u32) else {
+					self.error(LexErrorKind::InvalidUnicodeEscape, start);
+					// the braces and whatever is between them
+					if let Some(inside) = rest.strip_prefix('{') {
+						let len = body_len(inside, usize::MAX);
+						self.skip(1 + len + inside[len..].starts_with('}') as usize);
+					}
+					return None;
+				};
+
+				self.skip(digits.len() + 2);
+				c
+			},
+
+			c => {
+				self.error(LexErrorKind::UnknownEscape(c), start);
+				return None;
+			},
+		};
+
+		Some(escaped)
 	}
 
-	return tokens;
+	/// The next token or trivia, `None` after the Eof
+	fn next_lexed(&mut self) -> Option<(Lexed, Span)> {
+		let start = self.mark();
+		let lexed = match self.current() {
+			Some(c) => self.lex_token(c, start),
+			None if !self.done => {
+				self.done = true;
+				Lexed::Token(Token::Eof)
+			},
+			None => return None,
+		};
+
+		Some((lexed, self.span_from(start)))
+	}
+
+	fn current(&self) -> Option<char> { self.input[self.pos..].chars().next() }
+
+	/// Whether the byte `n` bytes ahead is `c`, which has to be ascii
+	fn peek(&self, n: usize, c: u8) -> bool { self.input.as_bytes().get(self.pos + n) == Some(&c) }
+
+	fn peek_digit(&self, n: usize) -> bool { self.input.as_bytes().get(self.pos + n).is_some_and(u8::is_ascii_digit) }
+
+	fn bump(&mut self) -> Option<char> {
+		let c = self.current()?;
+		self.pos += c.len_utf8();
+		if c == '\n' {
+			self.line += 1;
+			self.col = 1;
+		} else {
+			self.col += 1;
+		}
+
+		Some(c)
+	}
+
+	/// Skips `n` ascii chars that aren't newlines
+	fn skip(&mut self, n: usize) {
+		self.pos += n;
+		self.col += n as u32;
+	}
+
+	/// Up to the \n (or \r\n) at the end of the line
+	fn skip_line(&mut self) {
+		while self.pos < self.input.len() && !self.peek(0, b'\n') && !(self.peek(0, b'\r') && self.peek(1, b'\n')) { self.bump(); }
+	}
+
+	fn skip_while(&mut self, f: impl Fn(char) -> bool) {
+		while self.current().is_some_and(&f) { self.bump(); }
+	}
+
+	const fn mark(&self) -> Mark { Mark { pos: self.pos, line: self.line, col: self.col } }
+
+	const fn span_from(&self, start: Mark) -> Span {
+		Span { file: self.file, start: start.pos, end: self.pos, line: start.line, col: start.col }
+	}
+
+	fn error(&mut self, kind: LexErrorKind, start: Mark) {
+		let span = self.span_from(start);
+		self.errors.push(LexError { kind, span });
+	}
+}
+
+impl Iterator for Lexer<'_> {
+	type Item = SpannedToken;
+
+	fn next(&mut self) -> Option<SpannedToken> {
+		loop {
+			if let (Lexed::Token(token), span) = self.next_lexed()? {
+				return Some(SpannedToken { token, span });
+			}
+		}
+	}
 }
 
+impl std::iter::FusedIterator for Lexer<'_> {}
+
+/// Unicode XID, like Rust. The C backend mangles the ones C can't take
 #[inline(always)]
-const fn is_ident_start(c: char) -> bool { matches!(c, 'a'..='z' | 'A'..='Z' | '_') }
+fn is_ident_start(c: char) -> bool { c == '_' || unicode_ident::is_xid_start(c) }
 
 #[inline(always)]
-const fn is_ident_anywhere(c: char) -> bool { is_ident_start(c) || matches!(c, '0'..='9') }
+fn is_ident_anywhere(c: char) -> bool { unicode_ident::is_xid_continue(c) }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn round_trip(input: &str) -> Vec<LexError> {
+		let (tokens, errors) = lex_lossless(input, FileId(0));
+		assert_eq!(source_text(input, &tokens), input);
+		assert_eq!(tokens.last().map(|t| &t.token.token), Some(&Token::Eof));
+		errors
+	}
+
+	#[test]
+	fn lossless_round_trip() {
+		assert!(round_trip("").is_empty());
+		assert!(round_trip("main :: fn() -> int {\r\n\treturn 0; // done\r\n};\r\n").is_empty());
+		assert!(round_trip("/* outer /* inner */ still a comment */ x\r\r\n\n  /**/").is_empty());
+		assert!(round_trip("let s = \"ü\\n\";\n// no newline at the end").is_empty());
+	}
+
+	#[test]
+	fn lossless_round_trip_with_errors() {
+		let errors = round_trip("let c = '';\r\nlet n = 0x;\nlet s = \"\\q\";\n@ x");
+		assert_eq!(errors.len(), 4);
+		assert!(!round_trip("let s = \"unterminated\n").is_empty());
+		assert!(!round_trip("x /* unterminated /* nested */").is_empty());
+	}
+
+	#[test]
+	fn bad_escapes_are_skipped() {
+		// only the escape is wrong, the rest of the literal is still one character
+		let (_, errors) = lex("'\\u{110000}' '\\x80' \"\\u{zz}\"", FileId(0));
+		let kinds: Vec<_> = errors.into_iter().map(|e| e.kind).collect();
+		assert_eq!(kinds, [LexErrorKind::InvalidUnicodeEscape, LexErrorKind::InvalidHexEscape, LexErrorKind::InvalidUnicodeEscape]);
+	}
+
+	fn errors(input: &str) -> Vec<(LexErrorKind, u32, u32)> {
+		let (_, errors) = lex(input, FileId(0));
+		errors.into_iter().map(|e| (e.kind, e.span.line, e.span.col)).collect()
+	}
+
+	#[test]
+	fn errors_have_positions() {
+		assert_eq!(errors("let s = \"abc\nx"), [(LexErrorKind::UnterminatedString, 1, 9)]);
+		assert_eq!(errors("a :: 1;\nb :: 99999999999999999999;"), [(LexErrorKind::NumberTooLarge, 2, 6)]);
+		assert_eq!(errors("x $ y\n\t@ # z"), [
+			(LexErrorKind::UnexpectedCharacter('$'), 1, 3),
+			(LexErrorKind::UnexpectedCharacter('@'), 2, 2),
+			(LexErrorKind::UnexpectedCharacter('#'), 2, 4),
+		]);
+	}
+
+	#[test]
+	fn operator_at_the_end() {
+		for (input, token) in [("a /", Token::Slash), ("a :", Token::Colon), ("a -", Token::Hyphen), ("a =", Token::Equals)] {
+			let (tokens, errors) = lex(input, FileId(0));
+			assert!(errors.is_empty(), "{input:?}");
+			let tokens: Vec<_> = tokens.into_iter().map(|t| t.token).collect();
+			assert_eq!(tokens[1..], [token, Token::Eof], "{input:?}");
+		}
+	}
+}
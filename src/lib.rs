@@ -0,0 +1,57 @@
+#![allow(clippy::needless_return)]
+
+pub mod span;
+pub mod symbol;
+pub mod lexer;
+pub mod parser;
+pub mod typeck;
+pub mod consteval;
+pub mod diagnostic;
+
+use span::FileId;
+use lexer::Lexer;
+use parser::AstRoot;
+use typeck::Inferred;
+use consteval::{ Value, Defaults };
+use symbol::Symbol;
+use std::collections::HashMap;
+use diagnostic::Diagnostic;
+
+/// Lexes and parses a whole file, the diagnostics of both stages are sorted by position
+pub fn parse_source(input: &str, file: FileId) -> (AstRoot, Vec<Diagnostic>) {
+	let mut lexer = Lexer::new(input, file);
+	let (ast, parse_errors) = parser::parse(&mut lexer);
+
+	let mut diagnostics: Vec<Diagnostic> = lexer.errors.into_iter().map(Diagnostic::from)
+		.chain(parse_errors.into_iter().map(Diagnostic::from))
+		.collect();
+	diagnostics.sort_by_key(|d| d.span.start);
+
+	(ast, diagnostics)
+}
+
+/// Everything the backend (or the lsp) needs to know about a file
+pub struct Checked {
+	/// Every let has a type if there are no diagnostics
+	pub ast: AstRoot,
+	pub inferred: Vec<Inferred>,
+	/// The values of the top level constants
+	pub consts: HashMap<Symbol, Value>,
+	/// The initial values of the globals that have one
+	pub globals: HashMap<Symbol, Value>,
+	pub defaults: Defaults,
+	pub diagnostics: Vec<Diagnostic>,
+}
+
+/// `parse_source`, then evaluating the constants (and the initial values of the globals and defaults of struct members) and then typeck
+pub fn check_source(input: &str, file: FileId) -> Checked {
+	let (mut ast, mut diagnostics) = parse_source(input, file);
+	let (consts, globals, defaults, const_errors) = consteval::evaluate(&ast);
+	let (inferred, type_errors) = typeck::check(&mut ast, &consts);
+
+	diagnostics.extend(const_errors.into_iter().map(Diagnostic::from));
+	diagnostics.extend(type_errors.into_iter().map(Diagnostic::from));
+	diagnostics.sort_by_key(|d| d.span.start);
+
+	Checked { ast, inferred, consts, globals, defaults, diagnostics }
+}
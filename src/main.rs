@@ -1,13 +1,16 @@
 #![allow(clippy::needless_return)]
 
-mod lexer;
-mod parser;
-
 use std::fmt::Write;
-use parser::{ Statement, Expression, ConstAssignmentVal, Operator };
+use std::collections::{ HashMap, HashSet };
+use loki::{ span, parser };
+use loki::lexer::{ IntSuffix, FloatSuffix };
+use loki::symbol::Symbol;
+use loki::consteval::{ Value, Defaults };
+use parser::{ AstRoot, Statement, StatementKind, Expression, ExpressionKind, ConstAssignmentVal, Member, MemberDefault, Operator, Type, Block };
+use loki::diagnostic::Diagnostic;
 
 fn main() {
-	let mut args = std::env::args().into_iter().skip(1);
+	let mut args = std::env::args().skip(1);
 
 	let mut input_file = None;
 	let mut output_file = None;
@@ -88,57 +91,137 @@ fn compile(input_file: &str) -> String {
 		running_test = var_running_tests == "yes";
 	}
 
-	let input = std::fs::read_to_string(input_file).expect(&("Failed to open file ".to_string() + input_file));
-	let tokens = lexer::lex(&input);
-	// println!("{tokens:#?}");
-
-	let ast = parser::parse(tokens);
+	let input = std::fs::read_to_string(input_file).unwrap_or_else(|_| panic!("Failed to open file {input_file}"));
+	let loki::Checked { ast, consts, globals, defaults, diagnostics, .. } = loki::check_source(&input, span::FileId(0));
 	// println!("{ast:#?}");
 
+	// before the errors get reported, a test can expect one
+	if running_test {
+		for ca in ast.consts.iter().filter(|ca| ca.name.as_str().starts_with("__t_")) {
+			if let Some(value) = consts.get(&ca.name) { println!("{}={value}", ca.name) }
+		}
+	}
+
+	if !diagnostics.is_empty() {
+		report(input_file, diagnostics);
+	}
+
 	let mut program = "".to_string();
 
-	for ca in ast.0.iter() {
-		match &ca.1 {
-			ConstAssignmentVal::Function { args, return_type, .. } => {
-				let args = args.into_iter().map(|(name, type_)| type_.to_owned() + " " + &name).collect::<Vec<String>>().join(",");
-				write!(program,
-					"{ret} {name} ({args});",
-					ret = match return_type { Some(t) => t, None => "void" },
-					name = ca.0,
-				).unwrap();
+	// slices are structs, they get declared on demand before anything uses them
+	let mut slices = Vec::new();
+	for ca in ast.consts.iter() {
+		match &ca.val {
+			ConstAssignmentVal::Function { args, return_type, body } => {
+				args.iter().map(|(_, type_)| type_).chain(return_type).for_each(|type_| collect_slices(type_, &mut slices));
+				collect_slices_in_block(body, &mut slices);
 			},
-			_ => (),
+			ConstAssignmentVal::Struct(members) => members.iter().for_each(|member| collect_slices(&member.type_, &mut slices)),
+			ConstAssignmentVal::Union(variants) => variants.iter().flat_map(|(_, data)| data).for_each(|type_| collect_slices(type_, &mut slices)),
+			ConstAssignmentVal::Enum(_) | ConstAssignmentVal::Expression(_) => (),
 		}
 	}
+	ast.globals.iter().for_each(|global| collect_slices(&global.type_, &mut slices));
 
-	for const_assignment in ast.0 {
-		match const_assignment.1 {
-			ConstAssignmentVal::Function { args, return_type, body } => {
-				let args = args.into_iter().map(|(name, type_)| type_ + " " + &name).collect::<Vec<String>>().join(",");
-				let body = body.into_iter().map(serialize_statement).collect::<String>();
-				write!(program,
-					"{ret} {name} ({args}) {{ {body} }}",
-					ret = return_type.unwrap_or_else(|| "void".to_string()),
-					name = const_assignment.0,
-					args = args,
-					body = body,
-				).unwrap();
-			},
-
-			ConstAssignmentVal::Expression(expr) if matches!(expr, Expression::NumberLiteral(_)) && const_assignment.0.starts_with("__t_") => {
-				if let Expression::NumberLiteral(n) = expr {
-					if running_test { println!("{}={}", const_assignment.0, n) }
-				} else {
-					panic!();
-				}
+	if !slices.is_empty() {
+		program.push_str("#include <stddef.h>\n");
+	}
+
+	// C can't declare an enum without its values, they're simple enough to go first.
+	// a union's tags get their own enum, it's a struct with the tag and the data
+	for ca in ast.consts.iter() {
+		match &ca.val {
+			ConstAssignmentVal::Enum(variants) if variants.is_empty() => write!(program, "typedef int {};", c_type_name(ca.name)).unwrap(),
+			ConstAssignmentVal::Enum(variants) => {
+				let variants = variants.iter().map(|(variant, value)| format!("{} = {value}", c_variant_name(ca.name, *variant))).collect::<Vec<String>>().join(", ");
+				write!(program, "typedef enum {{ {variants} }} {};", c_type_name(ca.name)).unwrap();
 			},
+			ConstAssignmentVal::Union(variants) if !variants.is_empty() => {
+				let tags = variants.iter().map(|(variant, _)| c_variant_name(ca.name, *variant)).collect::<Vec<String>>().join(", ");
+				write!(program, "enum {{ {tags} }};").unwrap();
+			},
+			_ => (),
+		}
+	}
+
+	// declaring the structs first lets them point to each other
+	for ca in ast.consts.iter() {
+		if let ConstAssignmentVal::Struct(_) | ConstAssignmentVal::Union(_) = ca.val {
+			write!(program, "typedef struct {name} {name};", name = c_type_name(ca.name)).unwrap();
+		}
+	}
+
+	for slice in slices.iter() {
+		let Type::Slice(element) = slice else { unreachable!() };
+		let ptr = c_declaration(&Type::Pointer(element.clone()), "ptr");
+		write!(program, "typedef struct {{ {ptr}; size_t len; }} {name};", name = c_declaration(slice, "")).unwrap();
+	}
+
+	// only declared here, a struct might not be complete yet
+	for global in ast.globals.iter() {
+		write!(program, "extern {};", c_declaration(&global.type_, &c_name(global.name))).unwrap();
+	}
 
-			ConstAssignmentVal::Expression(_) => unimplemented!(),
+	for ca in ast.consts.iter() {
+		if let ConstAssignmentVal::Function { args, return_type, .. } = &ca.val {
+			write!(program, "{};", c_function_declaration(ca.name, args, return_type)).unwrap();
+		}
+	}
 
+	let initializers = Initializers::new(&ast, &defaults);
+	let unions = ast.consts.iter().filter(|ca| matches!(ca.val, ConstAssignmentVal::Union(_))).map(|ca| ca.name).collect();
+	let top_level = ast.consts.iter().map(|ca| ca.name).chain(ast.globals.iter().map(|global| global.name)).collect();
+	for ca in ast.consts.iter() {
+		match &ca.val {
 			ConstAssignmentVal::Struct(members) => {
-				let members = members.into_iter().map(|(name, type_)| type_ + " " + &name + ";").collect::<String>();
-				write!(program, "typedef struct {{ {members} }} {name};", name = const_assignment.0).unwrap();
+				let members = members.iter().map(|member| c_declaration(&member.type_, &c_name(member.name)) + ";").collect::<String>();
+				write!(program, "struct {name} {{ {members} }};", name = c_type_name(ca.name)).unwrap();
+			},
+			// a union without any data is only a tag
+			ConstAssignmentVal::Union(variants) => {
+				let data = variants.iter()
+					.filter_map(|(variant, data)| Some(c_declaration(data.as_ref()?, &c_name(*variant)) + ";"))
+					.collect::<String>();
+				let data = if data.is_empty() { data } else { format!(" union {{ {data} }} data;") };
+				write!(program, "struct {name} {{ int tag;{data} }};", name = c_type_name(ca.name)).unwrap();
 			},
+			// the enums are already there
+			ConstAssignmentVal::Function { .. } | ConstAssignmentVal::Expression(_) | ConstAssignmentVal::Enum(_) => (),
+		}
+	}
+
+	// they were evaluated already, and in loki they can use each other in any order.
+	// after the structs, a constant can be one
+	for ca in ast.consts.iter() {
+		if let ConstAssignmentVal::Expression(_) = ca.val {
+			let value = &consts[&ca.name];
+			write!(program, "{} = {};", c_declaration(&value.type_(), &format!("const {}", c_name(ca.name))), c_constant(value, &initializers)).unwrap();
+		}
+	}
+
+	let mut errors = Vec::new();
+	for const_assignment in ast.consts {
+		let ConstAssignmentVal::Function { args, return_type, body } = const_assignment.val else { continue };
+		// the value at the end of the body is returned
+		let destination = if return_type.is_some() { Destination::Return } else { Destination::Discard };
+		let locals = args.iter().map(|(name, _)| (*name, c_name(*name))).collect();
+		let mut lowering = Lowering { loops: Vec::new(), locals, initializers: &initializers, unions: &unions, top_level: &top_level };
+		match lowering.serialize_block(body, &destination) {
+			Ok(body) => write!(program, "{} {{ {body} }}", c_function_declaration(const_assignment.name, &args, &return_type)).unwrap(),
+			Err(e) => errors.push(e),
+		}
+	}
+
+	if !errors.is_empty() {
+		report(input_file, errors);
+	}
+
+	// the initial values were evaluated already, without one C makes it 0 (other than the defaults of a struct)
+	for global in ast.globals {
+		let declaration = c_declaration(&global.type_, &c_name(global.name));
+		match globals.get(&global.name).map(|value| c_constant(value, &initializers)).or_else(|| initializers.initializer(&global.type_)) {
+			Some(value) => write!(program, "{declaration} = {value};").unwrap(),
+			None => write!(program, "{declaration};").unwrap(),
 		}
 	}
 
@@ -147,44 +230,671 @@ fn compile(input_file: &str) -> String {
 	return program;
 }
 
-fn serialize_statement(statement: Statement) -> String {
-	match statement {
-		Statement::Return(expr) => format!("return {};", serialize_expression(expr)),
-		Statement::Let(name, type_, Some(val)) => format!("{type_} {name} = {};", serialize_expression(val)),
-		Statement::Let(name, type_, None) => format!("{type_} {name};"),
-		Statement::If(cond, body) => format!("if ({}) {}", serialize_expression(cond), serialize_statement(*body)),
-		Statement::While(cond, body) => format!("while ({}) {}", serialize_expression(cond), serialize_statement(*body)),
+fn report(input_file: &str, diagnostics: Vec<Diagnostic>) -> ! {
+	for diagnostic in diagnostics {
+		eprintln!("{input_file}:{}:{}: error: {}", diagnostic.span.line, diagnostic.span.col, diagnostic.message);
+	}
+
+	std::process::exit(1);
+}
+
+/// What each member of a struct gets when it's left out, as C initializers, by the name of the struct.
+/// Members without a default (that aren't structs with defaults themselves) stay uninitialized
+struct Initializers(HashMap<Symbol, Vec<(Symbol, String)>>);
+
+impl Initializers {
+	fn new(ast: &AstRoot, defaults: &Defaults) -> Self {
+		let structs: HashMap<Symbol, &[Member]> = ast.consts.iter().filter_map(|ca| match &ca.val {
+			ConstAssignmentVal::Struct(members) => Some((ca.name, members.as_slice())),
+			_ => None,
+		}).collect();
 
-		Statement::Compound(body) => format!("{{ {} }}", body.into_iter().map(serialize_statement).collect::<String>()),
-		Statement::Expression(expr) => serialize_expression(expr) + ";",
+		let mut initializers = Initializers(HashMap::new());
+		structs.keys().for_each(|name| initializers.add(*name, &structs, defaults));
+		initializers
+	}
+
+	/// Members that are structs need theirs first
+	fn add(&mut self, name: Symbol, structs: &HashMap<Symbol, &[Member]>, defaults: &Defaults) {
+		if self.0.contains_key(&name) { return }
+		// a struct that contains itself gets reported by C, this only has to not loop forever
+		self.0.insert(name, Vec::new());
+
+		let mut members = Vec::new();
+		for member in structs[&name].iter() {
+			let initializer = match (&member.default, &member.type_) {
+				(MemberDefault::Value(_), _) => defaults.get(&(name, member.name)).map(|value| {
+					self.add_in_value(value, structs, defaults);
+					c_constant(value, self)
+				}),
+				(MemberDefault::Required, Type::Named(inner)) if structs.contains_key(inner) => {
+					self.add(*inner, structs, defaults);
+					self.initializer(&member.type_)
+				},
+				// arrays of structs stay uninitialized too, that would need a loop
+				(MemberDefault::Required | MemberDefault::Uninitialized, _) => None,
+			};
+
+			if let Some(initializer) = initializer { members.push((member.name, initializer)) }
+		}
+
+		self.0.insert(name, members);
+	}
+
+	/// A default can be a struct too, it needs the defaults of that one
+	fn add_in_value(&mut self, value: &Value, structs: &HashMap<Symbol, &[Member]>, defaults: &Defaults) {
+		match value {
+			Value::Struct { name, members } => {
+				self.add(*name, structs, defaults);
+				members.iter().for_each(|(_, value)| self.add_in_value(value, structs, defaults));
+			},
+			Value::Union { data: Some(data), .. } => self.add_in_value(data, structs, defaults),
+			_ => (),
+		}
+	}
+
+	fn members(&self, name: Symbol) -> &[(Symbol, String)] {
+		self.0.get(&name).map_or(&[], Vec::as_slice)
+	}
+
+	/// For `let x: T;`, `None` when there's nothing to initialize
+	fn initializer(&self, type_: &Type) -> Option<String> {
+		let Type::Named(name) = type_ else { return None };
+		c_initializer(self.members(*name))
 	}
 }
 
-fn serialize_expression(expr: Expression) -> String {
-	match expr {
-		Expression::NumberLiteral(n) => n.to_string(),
-		Expression::StringLiteral(s) => '"'.to_string() + &s + "\"",
-		Expression::Ident(ident) => ident,
+/// `{ .x = 5, .y = 2 }`, `None` without any members
+fn c_initializer(members: &[(Symbol, String)]) -> Option<String> {
+	if members.is_empty() { return None }
+
+	let members = members.iter().map(|(name, initializer)| format!(".{} = {initializer}", c_name(*name))).collect::<Vec<String>>().join(", ");
+	Some(format!("{{ {members} }}"))
+}
 
-		Expression::BinaryOperator { op, left, right } => format!("({} {} {})", serialize_expression(*left), serialize_operator(op), serialize_expression(*right)),
-		Expression::UnaryOperator { op, operand } => format!("({}{})", serialize_operator(op), serialize_expression(*operand)),
+/// Where the value of an expression goes. C doesn't have if, loops and blocks as expressions,
+/// so they become statements that put the value of each branch there
+enum Destination {
+	/// Only evaluated for the side effects
+	Discard,
+	/// `to = value;`
+	Assign(String),
+	Return,
+}
 
-		Expression::FunctionCall(name, args) => {
-			let args = args.into_iter().map(serialize_expression).collect::<Vec<String>>().join(",");
-			format!("{name}({})", args)
-		},
+/// What lowering a function body needs to keep track of
+struct Lowering<'a> {
+	/// Innermost last
+	loops: Vec<LoopLabels>,
+	/// The arguments and lets in scope with their names in C, innermost last
+	locals: Vec<(Symbol, String)>,
+	initializers: &'a Initializers,
+	/// Their variants are structs with a tag, an enum's are just the value
+	unions: &'a HashSet<Symbol>,
+	/// Constants, functions, types and globals, a local can shadow them too
+	top_level: &'a HashSet<Symbol>,
+}
+
+struct LoopLabels {
+	label: Option<Symbol>,
+	/// The offset of the loop, it's unique in the function
+	id: usize,
+	/// How many C switches the current statement is in, inside of the loop. C's break would only leave the switch
+	switches: usize,
+	/// The C labels are only emitted when something jumps to them, unused labels are a warning
+	break_used: bool,
+	continue_used: bool,
+	/// C's continue would skip it, so it has to be a goto
+	has_epilogue: bool,
+}
+
+impl Lowering<'_> {
+	fn serialize_block(&mut self, block: Block, destination: &Destination) -> Result<String, Diagnostic> {
+		let scope = self.locals.len();
+		let mut c = String::new();
+		for statement in block.statements {
+			c += &self.serialize_statement(statement)?;
+		}
+
+		if let Some(value) = block.value {
+			c += &self.serialize_expression_to(*value, destination)?;
+		}
+
+		self.locals.truncate(scope);
+		Ok(c)
+	}
+
+	/// The C name for a new local. In loki it shadows anything with the same name, but in C it would be a redefinition
+	/// in the same scope, and in `int x = x + 1;` the x on the right would already be the new one. so a name that's
+	/// already taken gets a unique one, `offset` is where it's declared
+	fn declare(&mut self, name: Symbol, offset: usize) -> String {
+		let taken = self.top_level.contains(&name) || self.locals.iter().any(|(local, _)| *local == name);
+		let c_name = if taken { format!("{}_{offset}", mangle_all(name.as_str())) } else { c_name(name) };
+		self.locals.push((name, c_name.clone()));
+		c_name
+	}
+
+	fn serialize_statement(&mut self, statement: Statement) -> Result<String, Diagnostic> {
+		Ok(match statement.kind {
+			StatementKind::Return(expr) => self.serialize_expression_to(expr, &Destination::Return)?,
+			StatementKind::Let { name, name_span, type_, val, uninitialized } => {
+				let type_ = type_.expect("typeck gives every let a type");
+				// the value is lowered before the name is declared, it can still use whatever the name was before
+				match val {
+					Some(val) => {
+						let mut c = String::new();
+						let val = self.serialize_expression(val, &mut c)?;
+						write!(c, "{} = {val};", c_declaration(&type_, &self.declare(name, name_span.start))).unwrap();
+						c
+					},
+					None => match self.initializers.initializer(&type_).filter(|_| !uninitialized) {
+						Some(initializer) => format!("{} = {initializer};", c_declaration(&type_, &self.declare(name, name_span.start))),
+						None => format!("{};", c_declaration(&type_, &self.declare(name, name_span.start))),
+					},
+				}
+			},
+			StatementKind::Break(label) => self.serialize_jump("break", label),
+			StatementKind::Continue(label) => self.serialize_jump("continue", label),
+			StatementKind::Expression(expr) => self.serialize_expression_to(expr, &Destination::Discard)?,
+		})
+	}
+
+	/// `expr` as statements, with its value going to `destination`
+	fn serialize_expression_to(&mut self, expr: Expression, destination: &Destination) -> Result<String, Diagnostic> {
+		Ok(match expr.kind {
+			ExpressionKind::If { cond, body, else_body } => {
+				let mut c = String::new();
+				let cond = self.serialize_expression(*cond, &mut c)?;
+				write!(c, "if ({cond}) {{ {} }}", self.serialize_expression_to(*body, destination)?).unwrap();
+				if let Some(else_body) = else_body {
+					write!(c, " else {{ {} }}", self.serialize_expression_to(*else_body, destination)?).unwrap();
+				}
+
+				c
+			},
+			ExpressionKind::Block(block) => format!("{{ {} }}", self.serialize_block(block, destination)?),
+
+			// loops don't have a value
+			ExpressionKind::While { cond, body, label } => {
+				let (cond, prelude) = self.serialize_condition(*cond)?;
+				let head = if prelude.is_empty() { format!("while ({cond})") } else { "while (1)".to_string() };
+				self.serialize_loop(expr.span.start, label, head, prelude, *body, String::new())?
+			},
+			ExpressionKind::ForIn { var, start, end, body, label, type_ } => {
+				// start and end are evaluated once, in that order, before the variable exists. in `for i in 0..i` end is the i from before
+				let type_ = type_.expect("typeck knows the type of the variable");
+				let mut c = String::new();
+				let (start, end) = (self.serialize_expression(*start, &mut c)?, self.serialize_expression(*end, &mut c)?);
+				let (start_temporary, end_temporary) = (format!("{MANGLE_PREFIX}{}_start", expr.span.start), format!("{MANGLE_PREFIX}{}", expr.span.start));
+				write!(c, "{{ {} = {start}; {} = {end}; ", c_declaration(&type_, &start_temporary), c_declaration(&type_, &end_temporary)).unwrap();
+
+				let scope = self.locals.len();
+				let var = self.declare(var, expr.span.start);
+				let head = format!("for ({} = {start_temporary}; {var} < {end_temporary}; {var}++)", c_declaration(&type_, &var));
+				let body = self.serialize_loop(expr.span.start, label, head, String::new(), *body, String::new());
+				self.locals.truncate(scope);
+				c + &body? + " }"
+			},
+			ExpressionKind::For { init, cond, step, body, label } => {
+				// the init can be a few statements (a let with an if in it), so it goes before the loop in its own scope
+				let scope = self.locals.len();
+				let init = match init {
+					Some(init) => self.serialize_statement(*init)?,
+					None => String::new(),
+				};
+				let (cond, prelude) = cond.map(|cond| self.serialize_condition(*cond)).transpose()?.unwrap_or_default();
+				let cond = if prelude.is_empty() { cond } else { String::new() };
+				// a step with a temporary goes at the end of the body, after where continue goes to
+				let mut step_before = String::new();
+				let step = step.map(|step| self.serialize_expression(*step, &mut step_before)).transpose()?.unwrap_or_default();
+				let (step, epilogue) = if step_before.is_empty() { (step, String::new()) } else { (String::new(), format!("{step_before} {step};")) };
+				let head = format!("for (; {cond}; {step})");
+				let c = self.serialize_loop(expr.span.start, label, head, prelude, *body, epilogue);
+				self.locals.truncate(scope);
+				format!("{{ {init} {} }}", c?)
+			},
+
+			// the last arm is also the default when there's no else, so C knows that every path goes through one
+			ExpressionKind::Switch { value, arms, type_ } => {
+				let type_ = type_.expect("typeck knows what it switches on");
+				let mut before = String::new();
+				let value = self.serialize_expression(*value, &mut before)?;
+				// the data is taken out of it in the arms, it's only evaluated once
+				let temporary = format!("{MANGLE_PREFIX}{}", expr.span.start);
+				let has_else = arms.iter().any(|arm| arm.variants.is_empty());
+				let arm_count = arms.len();
+
+				if let Some(labels) = self.loops.last_mut() { labels.switches += 1 }
+				let mut cases = String::new();
+				let mut result = Ok(());
+				for (i, arm) in arms.into_iter().enumerate() {
+					let mut labels = arm.variants.iter().map(|(variant, _)| format!("case {}: ", c_variant_name(type_, *variant))).collect::<String>();
+					if arm.variants.is_empty() || (!has_else && i == arm_count - 1) { labels += "default: " }
+
+					let scope = self.locals.len();
+					let binding = match (arm.binding, arm.variants.first()) {
+						(Some(binding), Some((variant, _))) => {
+							let data = format!("{temporary}.data.{}", c_name(*variant));
+							format!("__typeof__({data}) {} = {data}; ", self.declare(binding, arm.body.span.start))
+						},
+						_ => String::new(),
+					};
+
+					let body = self.serialize_expression_to(arm.body, destination);
+					self.locals.truncate(scope);
+					match body {
+						Ok(body) => write!(cases, "{labels}{{ {binding}{body} }} break; ").unwrap(),
+						Err(e) => { result = Err(e); break },
+					}
+				}
+				if let Some(labels) = self.loops.last_mut() { labels.switches -= 1 }
+				result?;
+
+				if self.unions.contains(&type_) {
+					format!("{before}{{ __typeof__({value}) {temporary} = {value}; switch ({temporary}.tag) {{ {cases}}} }}")
+				} else {
+					format!("{before}switch ({value}) {{ {cases}}}")
+				}
+			},
+
+			_ => {
+				let mut c = String::new();
+				let value = self.serialize_expression(expr, &mut c)?;
+				match destination {
+					Destination::Discard => write!(c, "{value};").unwrap(),
+					Destination::Assign(to) => write!(c, "{to} = {value};").unwrap(),
+					Destination::Return => write!(c, "return {value};").unwrap(),
+				}
+
+				c
+			},
+		})
+	}
+
+	/// `head` is the C loop without the body. `prelude` goes before the body, and `epilogue` after it where
+	/// a continue goes to, it's the step of a for when it needs temporaries
+	fn serialize_loop(&mut self, id: usize, label: Option<Symbol>, head: String, prelude: String, body: Expression, epilogue: String) -> Result<String, Diagnostic> {
+		self.loops.push(LoopLabels { label, id, switches: 0, break_used: false, continue_used: false, has_epilogue: !epilogue.is_empty() });
+		let body = self.serialize_expression_to(body, &Destination::Discard);
+		let labels = self.loops.pop().unwrap();
+		let body = body?;
+
+		// a label has to be followed by a statement, hence the ;
+		let continue_label = if labels.continue_used { format!(" {MANGLE_PREFIX}continue_{id}:;") } else { String::new() };
+		let break_label = if labels.break_used { format!(" {MANGLE_PREFIX}break_{id}:;") } else { String::new() };
+		Ok(format!("{head} {{ {prelude}{body}{continue_label}{epilogue} }}{break_label}"))
+	}
+
+	/// The condition of a loop, and what has to be done before it every time. When that's not empty
+	/// the loop is `while (1)` and the prelude checks the condition
+	fn serialize_condition(&mut self, cond: Expression) -> Result<(String, String), Diagnostic> {
+		let mut before = String::new();
+		let cond = self.serialize_expression(cond, &mut before)?;
+		if before.is_empty() { return Ok((cond, before)) }
+
+		Ok((cond.clone(), format!("{before} if (!{cond}) break; ")))
+	}
+
+	/// `kind` is break or continue, C's own work for the innermost loop and outer ones get a goto
+	fn serialize_jump(&mut self, kind: &str, label: Option<Symbol>) -> String {
+		// the parser made sure the loop is there
+		let innermost = self.loops.len() - 1;
+		let target = match label {
+			Some(label) => self.loops.iter().rposition(|l| l.label == Some(label)).unwrap(),
+			None => innermost,
+		};
+
+		let in_switch = kind == "break" && self.loops[innermost].switches > 0;
+		let skips_epilogue = kind == "continue" && self.loops[innermost].has_epilogue;
+		if target == innermost && !in_switch && !skips_epilogue { return format!("{kind};") }
+
+		let target = &mut self.loops[target];
+		if kind == "break" { target.break_used = true } else { target.continue_used = true }
+		format!("goto {MANGLE_PREFIX}{kind}_{};", target.id)
+	}
+
+	/// An expression that C has as an expression too. The temporaries of the `Hoisted` in it are put in `before`,
+	/// which has to go before the statement the expression is in
+	fn serialize_expression(&mut self, expr: Expression, before: &mut String) -> Result<String, Diagnostic> {
+		Ok(match expr.kind {
+			ExpressionKind::NumberLiteral(n, suffix) => {
+				// unsuffixed decimals above i64::MAX don't have a type in C, hex ones become unsigned
+				let n = if n > i64::MAX as u64 { format!("{n:#X}") } else { n.to_string() };
+				n + match suffix {
+					None => "",
+					Some(IntSuffix::U) => "u",
+					Some(IntSuffix::L) => "l",
+					Some(IntSuffix::UL) => "ul",
+					Some(IntSuffix::LL) => "ll",
+					Some(IntSuffix::ULL) => "ull",
+				}
+			},
+			// debug formatting always has a . or an exponent so C sees a floating point constant
+			ExpressionKind::FloatLiteral(n, suffix) => format!("{n:?}") + match suffix {
+				None => "",
+				Some(FloatSuffix::F) => "f",
+				Some(FloatSuffix::L) => "l",
+			},
+			ExpressionKind::StringLiteral(s) => c_string_literal(&s),
+			ExpressionKind::CharLiteral(c) => c_char_literal(c),
+			ExpressionKind::Ident(ident) => match self.locals.iter().rfind(|(local, _)| *local == ident) {
+				Some((_, c_name)) => c_name.clone(),
+				None => c_name(ident),
+			},
+
+			// typeck turns p.x with a pointer into (*p).x
+			ExpressionKind::BinaryOperator { op: Operator::MemberAccess, left, right } if matches!(left.kind, ExpressionKind::UnaryOperator { op: Operator::Deref, .. }) => {
+				let ExpressionKind::UnaryOperator { operand, .. } = left.kind else { unreachable!() };
+				format!("({}->{})", self.serialize_expression(*operand, before)?, self.serialize_expression(*right, before)?)
+			},
+			ExpressionKind::BinaryOperator { op: op @ (Operator::LogicalAnd | Operator::LogicalOr), left, right } => {
+				let left = self.serialize_expression(*left, before)?;
+				let mut right_before = String::new();
+				let right = self.serialize_expression(*right, &mut right_before)?;
+				if right_before.is_empty() { return Ok(format!("({left} {} {right})", serialize_operator(op))) }
+
+				// the temporaries of right can only be made when it's evaluated
+				let temporary = format!("{MANGLE_PREFIX}{}_{}", expr.span.start, expr.span.end);
+				let when = if matches!(op, Operator::LogicalAnd) { "" } else { "!" };
+				write!(before, "int {temporary} = !!{left}; if ({when}{temporary}) {{ {right_before} {temporary} = !!{right}; }}").unwrap();
+				temporary
+			},
+			ExpressionKind::BinaryOperator { op, left, right } => format!("({} {} {})", self.serialize_expression(*left, before)?, serialize_operator(op), self.serialize_expression(*right, before)?),
+			ExpressionKind::UnaryOperator { op, operand } => format!("({}{})", serialize_operator(op), self.serialize_expression(*operand, before)?),
+
+			// anything that isn't just a name is in parens already
+			ExpressionKind::FunctionCall(callee, args) => {
+				let callee = self.serialize_expression(*callee, before)?;
+				let args = args.into_iter().map(|arg| self.serialize_expression(arg, before)).collect::<Result<Vec<String>, _>>()?.join(",");
+				format!("{callee}({args})")
+			},
+			// a compound literal, typeck names every member and the ones that were left out get their default
+			ExpressionKind::StructLiteral { name, members } => {
+				let given: Vec<Symbol> = members.iter().map(|(member, _)| member.expect("typeck names every member")).collect();
+				let mut members = members.into_iter()
+					.map(|(member, val)| Ok((member.unwrap(), self.serialize_expression(val, before)?)))
+					.collect::<Result<Vec<(Symbol, String)>, Diagnostic>>()?;
+				members.extend(self.initializers.members(name).iter().filter(|(member, _)| !given.contains(member)).cloned());
+
+				// C zeroes everything that isn't given, and {} is only allowed since C23
+				format!("(({}){})", c_type_name(name), c_initializer(&members).unwrap_or("{0}".to_string()))
+			},
+
+			ExpressionKind::Variant { type_, variant, data } => {
+				let tag = c_variant_name(type_, variant);
+				match data {
+					_ if !self.unions.contains(&type_) => tag,
+					Some(data) => format!("(({}){{ .tag = {tag}, .data.{} = {} }})", c_type_name(type_), c_name(variant), self.serialize_expression(*data, before)?),
+					None => format!("(({}){{ .tag = {tag} }})", c_type_name(type_)),
+				}
+			},
+
+			// the span of the value is unique, and a mangled name can't start with a digit
+			ExpressionKind::Hoisted { value, type_ } => {
+				let temporary = format!("{MANGLE_PREFIX}{}_{}", expr.span.start, expr.span.end);
+				let assignment = self.serialize_expression_to(*value, &Destination::Assign(temporary.clone()))?;
+				write!(before, "{}; {assignment} ", c_declaration(&type_, &temporary)).unwrap();
+				temporary
+			},
+			ExpressionKind::If { .. } | ExpressionKind::While { .. } | ExpressionKind::ForIn { .. } |
+			ExpressionKind::For { .. } | ExpressionKind::Block(_) | ExpressionKind::Switch { .. } => unreachable!("typeck hoists the ones that are used as values"),
+		})
 	}
 }
 
 fn serialize_operator(op: Operator) -> String {
 	match op {
 		Operator::Assign => '='.to_string(),
+		Operator::AddAssign => "+=".to_string(),
+		Operator::SubtractAssign => "-=".to_string(),
+		Operator::MultiplyAssign => "*=".to_string(),
+		Operator::DivideAssign => "/=".to_string(),
+		Operator::ModuloAssign => "%=".to_string(),
+		Operator::BitAndAssign => "&=".to_string(),
+		Operator::BitOrAssign => "|=".to_string(),
+		Operator::BitXorAssign => "^=".to_string(),
+		Operator::ShiftLeftAssign => "<<=".to_string(),
+		Operator::ShiftRightAssign => ">>=".to_string(),
 		Operator::Add | Operator::UnaryPlus => '+'.to_string(),
-		Operator::Subtract => '-'.to_string(),
+		Operator::Subtract | Operator::Negate => '-'.to_string(),
 		Operator::Multiply | Operator::Deref => '*'.to_string(),
+		Operator::Divide => '/'.to_string(),
+		Operator::Modulo => '%'.to_string(),
 		Operator::IsEqual => "==".to_string(),
+		Operator::IsNotEqual => "!=".to_string(),
 		Operator::IsLessThan => '<'.to_string(),
 		Operator::IsGreaterThan => '>'.to_string(),
+		Operator::IsLessOrEqual => "<=".to_string(),
+		Operator::IsGreaterOrEqual => ">=".to_string(),
+		// C's && and || already short circuit
+		Operator::LogicalAnd => "&&".to_string(),
+		Operator::LogicalOr => "||".to_string(),
+		Operator::LogicalNot => '!'.to_string(),
+		Operator::BitAnd | Operator::AddressOf => '&'.to_string(),
+		Operator::BitOr => '|'.to_string(),
+		Operator::BitXor => '^'.to_string(),
+		Operator::BitNot => '~'.to_string(),
+		Operator::ShiftLeft => "<<".to_string(),
+		Operator::ShiftRight => ">>".to_string(),
 		Operator::MemberAccess => '.'.to_string(),
 	}
 }
+
+/// The value with the suffix for its type. Unions and structs are initializers, they can only be used to initialize something
+fn c_constant(value: &Value, initializers: &Initializers) -> String {
+	match value {
+		Value::Integer(n, type_) => {
+			let suffix = match *type_ {
+				"unsigned" => "u",
+				"long" => "l",
+				"unsigned long" => "ul",
+				"long long" => "ll",
+				"unsigned long long" => "ull",
+				_ => "",
+			};
+
+			// the smallest long can't be written as -n, n would be too big for a long
+			if *n < -(i64::MAX as i128) { format!("({}{suffix} - 1)", n + 1) } else { format!("{n}{suffix}") }
+		},
+		Value::Float(n, type_) => format!("{n:?}") + match *type_ {
+			"float" => "f",
+			"long double" => "l",
+			_ => "",
+		},
+		Value::String(s) => c_string_literal(s),
+		Value::Enum { type_, variant, .. } => c_variant_name(*type_, *variant),
+		Value::Union { type_, variant, data: None } => format!("{{ .tag = {} }}", c_variant_name(*type_, *variant)),
+		Value::Union { type_, variant, data: Some(data) } => format!("{{ .tag = {}, .data.{} = {} }}", c_variant_name(*type_, *variant), c_name(*variant), c_constant(data, initializers)),
+		// like a struct literal, the members that were left out get their defaults
+		Value::Struct { name, members } => {
+			let mut members: Vec<(Symbol, String)> = members.iter().map(|(member, value)| (*member, c_constant(value, initializers))).collect();
+			let given: Vec<Symbol> = members.iter().map(|(member, _)| *member).collect();
+			members.extend(initializers.members(*name).iter().filter(|(member, _)| !given.contains(member)).cloned());
+			c_initializer(&members).unwrap_or("{0}".to_string())
+		},
+	}
+}
+
+/// Loki strings are utf-8, anything that isn't printable ascii gets emitted as octal escapes of its bytes
+fn c_string_literal(s: &str) -> String {
+	let mut literal = String::with_capacity(s.len() + 2);
+	literal.push('"');
+
+	for b in s.bytes() {
+		match b {
+			b'"' => literal.push_str("\\\""),
+			b'\\' => literal.push_str("\\\\"),
+			b'\n' => literal.push_str("\\n"),
+			b'\t' => literal.push_str("\\t"),
+			b'\r' => literal.push_str("\\r"),
+			b' '..=b'~' => literal.push(b as char),
+			// octal escapes stop after 3 digits, unlike \x which would eat any hex digit after it
+			b => write!(literal, "\\{b:03o}").unwrap(),
+		}
+	}
+
+	literal.push('"');
+	literal
+}
+
+fn c_char_literal(c: char) -> String {
+	match c {
+		'\'' => "'\\''".to_string(),
+		'\\' => "'\\\\'".to_string(),
+		'\n' => "'\\n'".to_string(),
+		'\t' => "'\\t'".to_string(),
+		'\r' => "'\\r'".to_string(),
+		' '..='~' => format!("'{c}'"),
+		'\0'..='\x7F' => format!("'\\{:03o}'", c as u32),
+		// doesn't fit in a C char, it's only useful as a number anyways
+		c => (c as u32).to_string(),
+	}
+}
+
+const MANGLE_PREFIX: &str = "__loki_";
+
+const C_KEYWORDS: &[&str] = &[
+	"auto", "break", "case", "char", "const", "continue", "default", "do", "double", "else", "enum", "extern",
+	"float", "for", "goto", "if", "inline", "int", "long", "register", "restrict", "return", "short", "signed",
+	"sizeof", "static", "struct", "switch", "typedef", "union", "unsigned", "void", "volatile", "while",
+	"_Alignas", "_Alignof", "_Atomic", "_Bool", "_Complex", "_Generic", "_Imaginary", "_Noreturn", "_Static_assert", "_Thread_local",
+	// C23
+	"alignas", "alignof", "bool", "constexpr", "false", "nullptr", "static_assert", "thread_local", "true", "typeof", "typeof_unqual",
+];
+
+/// The keywords that are types by themselves
+const C_TYPES: &[&str] = &["char", "double", "float", "int", "long", "short", "signed", "unsigned", "void", "_Bool", "_Complex", "bool"];
+
+/// Names of variables, functions and members
+fn c_name(name: Symbol) -> String {
+	mangle(name.as_str(), true)
+}
+
+/// Every variant of every enum needs its own name in C, a type can't have a . in it so they can't clash
+fn c_variant_name(type_: Symbol, variant: Symbol) -> String {
+	mangle_all(&format!("{type_}.{variant}"))
+}
+
+/// C's own types (like int) are keywords that have to be kept, any other type is a struct, enum or union
+fn c_type_name(name: Symbol) -> String {
+	mangle(name.as_str(), !C_TYPES.contains(&name.as_str()))
+}
+
+fn c_function_declaration(name: Symbol, args: &[(Symbol, Type)], return_type: &Option<Type>) -> String {
+	let args = args.iter().map(|(name, type_)| c_declaration(type_, &c_name(*name))).collect::<Vec<String>>().join(",");
+	let declarator = format!("{}({args})", c_name(name));
+	match return_type {
+		Some(return_type) => c_declaration(return_type, &declarator),
+		None => "void ".to_string() + &declarator,
+	}
+}
+
+/// C puts the type around the name, inside out: `int *x`, `int x[4]`, `int (*x)(char)`, `int (*x[4])(void)`.
+/// `declarator` is the name (or a function with its args), it can be empty for just the type
+fn c_declaration(type_: &Type, declarator: &str) -> String {
+	let named = |name: String| if declarator.is_empty() { name } else { name + " " + declarator };
+	// [] and () bind tighter than *, so *x[4] would be an array of pointers
+	let grouped = || if declarator.starts_with('*') { format!("({declarator})") } else { declarator.to_string() };
+
+	match type_ {
+		Type::Named(name) => named(c_type_name(*name)),
+		// they don't have a name in C, the loki type (which can't clash with an identifier) gets mangled
+		Type::Slice(_) | Type::Generic(..) => named(mangle_all(&type_.to_string())),
+
+		Type::Pointer(inner) => c_declaration(inner, &format!("*{declarator}")),
+		Type::Array(inner, n) => c_declaration(inner, &format!("{}[{n}]", grouped())),
+		// loki function types are function pointers
+		Type::Function { args, return_type } => {
+			let args = if args.is_empty() { "void".to_string() } else { args.iter().map(|arg| c_declaration(arg, "")).collect::<Vec<String>>().join(",") };
+			let declarator = format!("(*{declarator})({args})");
+			match return_type {
+				Some(return_type) => c_declaration(return_type, &declarator),
+				None => "void ".to_string() + &declarator,
+			}
+		},
+	}
+}
+
+/// Slices used anywhere in `type_`, the ones inside come first so they're declared first
+fn collect_slices(type_: &Type, slices: &mut Vec<Type>) {
+	match type_ {
+		Type::Named(_) => (),
+		Type::Pointer(inner) | Type::Array(inner, _) => collect_slices(inner, slices),
+		Type::Slice(inner) => {
+			collect_slices(inner, slices);
+			if !slices.contains(type_) { slices.push(type_.clone()) }
+		},
+		Type::Function { args, return_type } => args.iter().chain(return_type.as_deref()).for_each(|type_| collect_slices(type_, slices)),
+		Type::Generic(_, args) => args.iter().for_each(|type_| collect_slices(type_, slices)),
+	}
+}
+
+fn collect_slices_in_block(block: &Block, slices: &mut Vec<Type>) {
+	block.statements.iter().for_each(|statement| collect_slices_in_statement(statement, slices));
+	if let Some(value) = &block.value { collect_slices_in_expression(value, slices) }
+}
+
+fn collect_slices_in_statement(statement: &Statement, slices: &mut Vec<Type>) {
+	match &statement.kind {
+		StatementKind::Let { type_, val, .. } => {
+			if let Some(type_) = type_ { collect_slices(type_, slices) }
+			if let Some(val) = val { collect_slices_in_expression(val, slices) }
+		},
+		StatementKind::Return(expr) | StatementKind::Expression(expr) => collect_slices_in_expression(expr, slices),
+		StatementKind::Break(_) | StatementKind::Continue(_) => (),
+	}
+}
+
+/// Only blocks have types in them, but they can be anywhere
+fn collect_slices_in_expression(expr: &Expression, slices: &mut Vec<Type>) {
+	match &expr.kind {
+		ExpressionKind::Block(block) => collect_slices_in_block(block, slices),
+		ExpressionKind::If { cond, body, else_body } => {
+			[cond, body].into_iter().chain(else_body).for_each(|expr| collect_slices_in_expression(expr, slices));
+		},
+		ExpressionKind::While { cond, body, .. } => [cond, body].into_iter().for_each(|expr| collect_slices_in_expression(expr, slices)),
+		ExpressionKind::ForIn { start, end, body, .. } => [start, end, body].into_iter().for_each(|expr| collect_slices_in_expression(expr, slices)),
+		ExpressionKind::For { init, cond, step, body, .. } => {
+			if let Some(init) = init { collect_slices_in_statement(init, slices) }
+			cond.iter().chain(step).chain([body]).for_each(|expr| collect_slices_in_expression(expr, slices));
+		},
+		ExpressionKind::BinaryOperator { left, right, .. } => [left, right].into_iter().for_each(|expr| collect_slices_in_expression(expr, slices)),
+		ExpressionKind::UnaryOperator { operand, .. } => collect_slices_in_expression(operand, slices),
+		ExpressionKind::FunctionCall(callee, args) => [callee.as_ref()].into_iter().chain(args).for_each(|expr| collect_slices_in_expression(expr, slices)),
+		ExpressionKind::StructLiteral { members, .. } => members.iter().for_each(|(_, val)| collect_slices_in_expression(val, slices)),
+		ExpressionKind::Variant { data, .. } => data.iter().for_each(|data| collect_slices_in_expression(data, slices)),
+		ExpressionKind::Hoisted { value, type_ } => {
+			collect_slices(type_, slices);
+			collect_slices_in_expression(value, slices);
+		},
+		ExpressionKind::Switch { value, arms, .. } => {
+			[value.as_ref()].into_iter().chain(arms.iter().map(|arm| &arm.body)).for_each(|expr| collect_slices_in_expression(expr, slices));
+		},
+		ExpressionKind::NumberLiteral(..) | ExpressionKind::FloatLiteral(..) | ExpressionKind::StringLiteral(_) |
+		ExpressionKind::CharLiteral(_) | ExpressionKind::Ident(_) => (),
+	}
+}
+
+/// Identifiers C can't take (not ascii, or keywords if `keywords`) get mangled with `mangle_all`.
+/// Names that already start with `__loki_` are mangled too so only mangled names have that prefix
+fn mangle(name: &str, keywords: bool) -> String {
+	let c_compatible = name.is_ascii() && !name.starts_with(MANGLE_PREFIX) && !(keywords && C_KEYWORDS.contains(&name));
+	if c_compatible { return name.to_string() }
+
+	mangle_all(name)
+}
+
+/// `__loki_` and then `name` with `_` as `__` and any other non alphanumeric char as `_u{hex}_`,
+/// so two different names can't end up the same
+fn mangle_all(name: &str) -> String {
+	let mut mangled = MANGLE_PREFIX.to_string();
+	for c in name.chars() {
+		match c {
+			'_' => mangled.push_str("__"),
+			c if c.is_ascii_alphanumeric() => mangled.push(c),
+			c => write!(mangled, "_u{:x}_", c as u32).unwrap(),
+		}
+	}
+
+	mangled
+}
@@ -1,82 +1,353 @@
-use crate::lexer::Token;
+use crate::lexer::{ Token, SpannedToken, IntSuffix, FloatSuffix };
+use crate::span::Span;
+use crate::symbol::Symbol;
+use std::collections::{ HashMap, VecDeque };
 
 /*
-For now i think that the root can only be const assignments
-
-Root = (const_assignmnet)*
+Root = (const_assignmnet | global)*
 // TODO: maybe const assignments don't need a ';'
 const_assignment = ident "::" expr;
+global = ident ":" type ("=" expr)? ";"
 
 expr = func | ident | literal
-func = "fn" "(" (ident ":" ident)* ")" ("->" ident)? "{" (statement)* "}"
+func = "fn" "(" (ident ":" type)* ")" ("->" type)? "{" (statement)* "}"
+type = (ident ("<" type ("," type)* ">")? | "fn" "(" (type ",")* ")" ("->" type)? | "(" type ")") ("*" | "[" number? "]")*
 */
 
 #[derive(Debug)]
-pub struct AstRoot(pub Vec<ConstAssignment>);
+pub struct AstRoot {
+	pub consts: Vec<ConstAssignment>,
+	pub globals: Vec<Global>,
+}
+
+#[derive(Debug)]
+pub struct ConstAssignment {
+	pub name: Symbol,
+	pub val: ConstAssignmentVal,
+	pub span: Span,
+	/// From the `///` lines right before it
+	pub doc: Option<String>,
+}
 
+/// `counter : int = 0;`, a global variable, the value has to be known at compile time
 #[derive(Debug)]
-pub struct ConstAssignment(pub String, pub ConstAssignmentVal);
+pub struct Global {
+	pub name: Symbol,
+	pub type_: Type,
+	pub val: Option<Expression>,
+	pub span: Span,
+	pub doc: Option<String>,
+}
 
 #[derive(Debug)]
 pub enum ConstAssignmentVal {
-	Function { args: Vec<(String, String)>, return_type: Option<String>, body: Vec<Statement> },
-	Struct(Vec<(String, String)>),
+	Function { args: Vec<(Symbol, Type)>, return_type: Option<Type>, body: Block },
+	Struct(Vec<Member>),
+	/// With every value known, one without `= n` is one more than the one before it (or 0)
+	Enum(Vec<(Symbol, i64)>),
+	/// A tagged union, `Circle(float)` has data and `Empty` doesn't
+	Union(Vec<(Symbol, Option<Type>)>),
 	Expression(Expression),
 }
 
+/// `x: int = 5`
+#[derive(Debug)]
+pub struct Member {
+	pub name: Symbol,
+	pub type_: Type,
+	pub default: MemberDefault,
+}
+
+#[derive(Debug)]
+pub enum MemberDefault {
+	/// Struct literals have to give it, `let p: Point;` gives it what `let x: T;` would
+	Required,
+	/// Known at compile time like the initial value of a global
+	Value(Expression),
+	/// `x: int = ---`, it can be left out and then it's left uninitialized
+	Uninitialized,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+	/// `int`, `Point`
+	Named(Symbol),
+	/// `int*`
+	Pointer(Box<Type>),
+	/// `int[4]`
+	Array(Box<Type>, u64),
+	/// `int[]`, a pointer and a length
+	Slice(Box<Type>),
+	/// `fn(int, char*) -> int`, it's a pointer to the function like in C
+	Function { args: Vec<Type>, return_type: Option<Box<Type>> },
+	/// `List<int>`
+	Generic(Symbol, Vec<Type>),
+}
+
 #[derive(Debug)]
 pub enum Operator {
 	Assign,
+	AddAssign,
+	SubtractAssign,
+	MultiplyAssign,
+	DivideAssign,
+	ModuloAssign,
+	BitAndAssign,
+	BitOrAssign,
+	BitXorAssign,
+	ShiftLeftAssign,
+	ShiftRightAssign,
 	Add,
 	Subtract,
 	Multiply,
+	Divide,
+	Modulo,
 	Deref,
+	AddressOf,
 	IsEqual,
+	IsNotEqual,
 	UnaryPlus,
+	Negate,
 	IsLessThan,
 	IsGreaterThan,
+	IsLessOrEqual,
+	IsGreaterOrEqual,
+	LogicalAnd,
+	LogicalOr,
+	LogicalNot,
+	BitAnd,
+	BitOr,
+	BitXor,
+	BitNot,
+	ShiftLeft,
+	ShiftRight,
 	MemberAccess,
 }
 
 #[derive(Debug)]
-pub enum Expression {
-	NumberLiteral(i64),
+pub struct Expression {
+	pub kind: ExpressionKind,
+	pub span: Span,
+}
+
+#[derive(Debug)]
+pub enum ExpressionKind {
+	NumberLiteral(u64, Option<IntSuffix>),
+	FloatLiteral(f64, Option<FloatSuffix>),
 	StringLiteral(String),
-	Ident(String),
+	CharLiteral(char),
+	Ident(Symbol),
 
 	BinaryOperator { op: Operator, left: Box<Expression>, right: Box<Expression> },
 	UnaryOperator { op: Operator, operand: Box<Expression> },
 
-	FunctionCall(String, Vec<Expression>),
+	/// The function can be any expression, `table.handler(x)`, `get_fn()(1)`
+	FunctionCall(Box<Expression>, Vec<Expression>),
+
+	/// `else if` is an `If` in the else branch
+	If { cond: Box<Expression>, body: Box<Expression>, else_body: Option<Box<Expression>> },
+	While { cond: Box<Expression>, body: Box<Expression>, label: Option<Symbol> },
+	/// `for i in 0..n`, `end` isn't included. `type_` is the type of `i`, typeck fills it in
+	ForIn { var: Symbol, start: Box<Expression>, end: Box<Expression>, body: Box<Expression>, label: Option<Symbol>, type_: Option<Type> },
+	/// `for (let i: int = 0; i < n; i += 1)`, like C
+	For { init: Option<Box<Statement>>, cond: Option<Box<Expression>>, step: Option<Box<Expression>>, body: Box<Expression>, label: Option<Symbol> },
+	Block(Block),
+	/// `Point{ x = 1, y = 2 }` has the names, `Point{1, 2}` doesn't until typeck fills them in
+	StructLiteral { name: Symbol, members: Vec<(Option<Symbol>, Expression)> },
+	/// `Color.Red` or `Shape.Circle(2.0)`, the parser sees a member access or a call and typeck turns them into this
+	Variant { type_: Symbol, variant: Symbol, data: Option<Box<Expression>> },
+	/// `switch (shape) { Circle(r) => r * r, Empty => 0 }`, the arms have to cover every variant (or there's an `else`).
+	/// `type_` is the enum or union, typeck fills it in
+	Switch { value: Box<Expression>, arms: Vec<SwitchArm>, type_: Option<Symbol> },
+	/// An `if`, block or switch used as a value inside of another expression, typeck wraps them in this.
+	/// C doesn't have them as expressions, so the backend puts the value in a temporary before the statement
+	Hoisted { value: Box<Expression>, type_: Type },
 }
 
+/// `Red | Green => body` or `Circle(r) => body`
 #[derive(Debug)]
-pub enum Statement {
-	Return(Expression),
-	Let(String, String, Option<Expression>),
-	// TODO: make if and while an expression
-	If(Expression, Box<Statement>),
-	While(Expression, Box<Statement>),
+pub struct SwitchArm {
+	/// With their spans, empty for `else`
+	pub variants: Vec<(Symbol, Span)>,
+	/// For the data of the variant, there can only be one variant then
+	pub binding: Option<Symbol>,
+	pub body: Expression,
+}
 
-	// TODO: make this an expr
-	Compound(Vec<Statement>),
+#[derive(Debug)]
+pub struct Block {
+	pub statements: Vec<Statement>,
+	/// The expression at the end without a `;`
+	pub value: Option<Box<Expression>>,
+}
+
+#[derive(Debug)]
+pub struct Statement {
+	pub kind: StatementKind,
+	pub span: Span,
+}
+
+#[derive(Debug)]
+pub enum StatementKind {
+	Return(Expression),
+	/// Without a type it gets inferred from `val` by typeck.
+	/// `uninitialized` is for `let p: Point = ---;`, the defaults of the struct aren't applied then
+	Let { name: Symbol, name_span: Span, type_: Option<Type>, val: Option<Expression>, uninitialized: bool },
+	/// With the label of the loop, otherwise it's the innermost one
+	Break(Option<Symbol>),
+	Continue(Option<Symbol>),
 	Expression(Expression),
 }
 
-pub fn parse(tokens: Vec<Token>) -> AstRoot {
-	Parser { tokens: &tokens, pos: 0 }.parse()
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseErrorKind {
+	ExpectedToken { expected: Token, found: Token },
+	ExpectedIdent(Token),
+	ExpectedExpression(Token),
+	MissingTypeAnnotation,
+	InvalidAssignmentTarget,
+	InvalidAddressOf,
+	/// `break` or `continue`
+	OutsideOfLoop(Token),
+	UnknownLabel(Symbol),
+	ExpectedLoop(Token),
+	MixedStructLiteral,
+	ExpectedNumber(Token),
+	EnumValueOutOfRange,
+	DuplicateElse,
+	BindingWithAlternatives,
+	/// Nothing can declare one yet, so the backend wouldn't know what to emit
+	GenericType(Type),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+	pub kind: ParseErrorKind,
+	pub span: Span,
+}
+
+impl std::fmt::Display for Type {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		// a * or [] after a function type with a return type would go on the return type
+		let postfix_on = |f: &mut std::fmt::Formatter<'_>, inner: &Type| match inner {
+			Type::Function { return_type: Some(_), .. } => write!(f, "({inner})"),
+			_ => write!(f, "{inner}"),
+		};
+
+		match self {
+			Type::Named(name) => write!(f, "{name}"),
+			Type::Pointer(inner) => { postfix_on(f, inner)?; f.write_str("*") },
+			Type::Array(inner, n) => { postfix_on(f, inner)?; write!(f, "[{n}]") },
+			Type::Slice(inner) => { postfix_on(f, inner)?; f.write_str("[]") },
+			Type::Function { args, return_type } => {
+				let args = args.iter().map(Type::to_string).collect::<Vec<_>>().join(", ");
+				write!(f, "fn({args})")?;
+				match return_type {
+					Some(return_type) => write!(f, " -> {return_type}"),
+					None => Ok(()),
+				}
+			},
+			Type::Generic(name, args) => {
+				let args = args.iter().map(Type::to_string).collect::<Vec<_>>().join(", ");
+				write!(f, "{name}<{args}>")
+			},
+		}
+	}
+}
+
+impl std::fmt::Display for ParseError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match &self.kind {
+			ParseErrorKind::ExpectedToken { expected, found } => write!(f, "expected {expected}, found {found}"),
+			ParseErrorKind::ExpectedIdent(found) => write!(f, "expected identifier, found {found}"),
+			ParseErrorKind::ExpectedExpression(found) => write!(f, "expected expression, found {found}"),
+			ParseErrorKind::MissingTypeAnnotation => write!(f, "a let without a value needs a type annotation"),
+			ParseErrorKind::InvalidAssignmentTarget => write!(f, "can only assign to variables, dereferences and members"),
+			ParseErrorKind::InvalidAddressOf => write!(f, "can only take the address of variables, dereferences and members"),
+			ParseErrorKind::OutsideOfLoop(token) => write!(f, "{token} outside of a loop"),
+			ParseErrorKind::UnknownLabel(label) => write!(f, "no loop labeled '{label}' around this"),
+			ParseErrorKind::ExpectedLoop(found) => write!(f, "expected a loop after the label, found {found}"),
+			ParseErrorKind::MixedStructLiteral => write!(f, "either all of the members need a name or none of them"),
+			ParseErrorKind::ExpectedNumber(found) => write!(f, "expected a number, found {found}"),
+			ParseErrorKind::EnumValueOutOfRange => write!(f, "the values of an enum have to fit in an int"),
+			ParseErrorKind::DuplicateElse => write!(f, "the switch already has an else"),
+			ParseErrorKind::BindingWithAlternatives => write!(f, "only an arm with a single variant can bind its data"),
+			ParseErrorKind::GenericType(type_) => write!(f, "generic types like '{type_}' can't be used yet"),
+		}
+	}
+}
+
+type ParseResult<T> = Result<T, ParseError>;
+
+/// Pulls `tokens` as it goes (a `lexer::Lexer` works), if they run out before a `Token::Eof` it's treated like there was one.
+/// Returns whatever could be parsed, the parser skips to the next `;`, `}` or top level `::` after an error
+pub fn parse(tokens: impl IntoIterator<Item = SpannedToken>) -> (AstRoot, Vec<ParseError>) {
+	let mut parser = Parser {
+		tokens: tokens.into_iter(),
+		lookahead: VecDeque::with_capacity(LOOKAHEAD),
+		prev_span: Span::default(),
+		errors: Vec::new(),
+		docs: HashMap::new(),
+		loops: Vec::new(),
+		no_struct_literal: false,
+	};
+
+	parser.fill();
+	let root = parser.parse();
+	(root, parser.errors)
+}
+
+impl Expression {
+	/// Whether it can be on the left of an assignment
+	pub fn is_lvalue(&self) -> bool {
+		matches!(self.kind,
+			ExpressionKind::Ident(_) |
+			ExpressionKind::UnaryOperator { op: Operator::Deref, .. } |
+			ExpressionKind::BinaryOperator { op: Operator::MemberAccess, .. }
+		)
+	}
+
+	/// `if`, loops, blocks and switches, C only has them as statements
+	pub fn is_block_like(&self) -> bool {
+		matches!(self.kind,
+			ExpressionKind::If { .. } | ExpressionKind::While { .. } | ExpressionKind::ForIn { .. } |
+			ExpressionKind::For { .. } | ExpressionKind::Block(_) | ExpressionKind::Switch { .. }
+		)
+	}
 }
 
 impl Operator {
 	fn to_binary_op(token: &Token) -> Option<Self> {
 		match token {
 			Token::Equals => Some(Self::Assign),
+			Token::PlusEquals => Some(Self::AddAssign),
+			Token::HyphenEquals => Some(Self::SubtractAssign),
+			Token::StarEquals => Some(Self::MultiplyAssign),
+			Token::SlashEquals => Some(Self::DivideAssign),
+			Token::PercentEquals => Some(Self::ModuloAssign),
+			Token::AmpersandEquals => Some(Self::BitAndAssign),
+			Token::PipeEquals => Some(Self::BitOrAssign),
+			Token::CaretEquals => Some(Self::BitXorAssign),
+			Token::AngleBracketOpenOpenEquals => Some(Self::ShiftLeftAssign),
+			Token::AngleBracketCloseCloseEquals => Some(Self::ShiftRightAssign),
 			Token::Plus => Some(Self::Add),
 			Token::Hyphen => Some(Self::Subtract),
 			Token::Star => Some(Self::Multiply),
+			Token::Slash => Some(Self::Divide),
+			Token::Percent => Some(Self::Modulo),
 			Token::EqualsEquals => Some(Self::IsEqual),
+			Token::ExclamationEquals => Some(Self::IsNotEqual),
 			Token::AngleBracketOpen => Some(Self::IsLessThan),
 			Token::AngleBracketClose => Some(Self::IsGreaterThan),
+			Token::AngleBracketOpenEquals => Some(Self::IsLessOrEqual),
+			Token::AngleBracketCloseEquals => Some(Self::IsGreaterOrEqual),
+			Token::AmpersandAmpersand => Some(Self::LogicalAnd),
+			Token::PipePipe => Some(Self::LogicalOr),
+			Token::Ampersand => Some(Self::BitAnd),
+			Token::Pipe => Some(Self::BitOr),
+			Token::Caret => Some(Self::BitXor),
+			Token::AngleBracketOpenOpen => Some(Self::ShiftLeft),
+			Token::AngleBracketCloseClose => Some(Self::ShiftRight),
 			Token::Period => Some(Self::MemberAccess),
 			_ => None,
 		}
@@ -85,327 +356,1024 @@ impl Operator {
 	fn to_unary_op(token: &Token) -> Option<Self> {
 		match token {
 			Token::Plus => Some(Self::UnaryPlus),
+			Token::Hyphen => Some(Self::Negate),
 			Token::Star => Some(Self::Deref),
+			Token::Ampersand => Some(Self::AddressOf),
+			Token::Exclamation => Some(Self::LogicalNot),
+			Token::Tilde => Some(Self::BitNot),
 			_ => None,
 		}
 	}
 }
 
-macro_rules! consume_unwrap {
-	($self:ident, $token:expr) => { $self.consume(&$token).expect(&format!("Expected {:?} but found {:?}", $token, $self.at())) };
+/// `at()` and one token after it
+const LOOKAHEAD: usize = 2;
+
+struct Parser<I: Iterator<Item = SpannedToken>> {
+	tokens: I,
+	/// Always has `LOOKAHEAD` tokens, once the input runs out they're Eof
+	lookahead: VecDeque<SpannedToken>,
+	/// Of the last consumed token
+	prev_span: Span,
+	errors: Vec<ParseError>,
+	/// Doc comments by the byte offset of the token after them, they only mean something before a const assignment
+	docs: HashMap<usize, String>,
+	/// Labels of the loops around the current statement, for checking break and continue
+	loops: Vec<Option<Symbol>>,
+	/// In `for i in 0..n {}`, `n {` isn't a struct literal. Parens turn it back on
+	no_struct_literal: bool,
 }
 
-struct Parser<'a> {
-	tokens: &'a [Token],
-	pos: usize,
-}
-
-impl<'a> Parser<'a> {
-	fn parse(mut self) -> AstRoot {
-		let mut root = Vec::new();
-		while self.pos < self.tokens.len() {
-			root.push(self.parse_const_assignment());
+impl<I: Iterator<Item = SpannedToken>> Parser<I> {
+	fn parse(&mut self) -> AstRoot {
+		let mut root = AstRoot { consts: Vec::new(), globals: Vec::new() };
+		while *self.at() != Token::Eof {
+			let result = if *self.peek(1) == Token::Colon {
+				self.parse_global().map(|global| root.globals.push(global))
+			} else {
+				self.parse_const_assignment().map(|const_assignment| root.consts.push(const_assignment))
+			};
+
+			if let Err(e) = result {
+				self.error(e);
+				self.recover_top_level();
+			}
 		}
 
-		return AstRoot(root);
+		return root;
 	}
 
 	#[inline(always)]
-	const fn at(&self) -> &Token { &self.tokens[self.pos] }
+	fn at(&self) -> &Token { &self.lookahead[0].token }
 
-	// fn expect_ident(&self) -> Option<String> { match self.at() {
-	// 	Token::Ident(ident) => Some(ident.clone()),
-	// 	_ => None,
-	// } }
+	/// `n` has to be less than `LOOKAHEAD`
+	#[inline(always)]
+	fn peek(&self, n: usize) -> &Token { &self.lookahead[n].token }
 
-	#[must_use]
-	fn consume_ident(&mut self) -> Option<String> {
-		let at = self.at().ident();
-		if at.is_some() {
-			self.pos += 1;
+	#[inline(always)]
+	fn span(&self) -> Span { self.lookahead[0].span }
+
+	/// From `start` to the end of the last consumed token
+	#[inline(always)]
+	const fn span_from(&self, start: Span) -> Span { start.to(self.prev_span) }
+
+	fn bump(&mut self) {
+		// fill() keeps it full so there's always a token
+		let token = self.lookahead.pop_front().unwrap();
+		if !self.docs.is_empty() { self.docs.remove(&token.span.start); }
+		self.prev_span = token.span;
+		self.fill();
+	}
+
+	fn fill(&mut self) {
+		while self.lookahead.len() < LOOKAHEAD {
+			let token = self.next_token();
+			self.lookahead.push_back(token);
+		}
+	}
+
+	/// The next token that isn't a doc comment, the doc comments before it go into `docs`
+	fn next_token(&mut self) -> SpannedToken {
+		let mut doc = String::new();
+		loop {
+			let Some(token) = self.tokens.next() else {
+				// the lexer stops after the Eof, but the parser can keep looking at it
+				let span = self.lookahead.back().map_or(self.prev_span, |last| last.span);
+				return SpannedToken { token: Token::Eof, span };
+			};
+
+			if let Token::DocComment(text) = &token.token {
+				if !doc.is_empty() { doc.push('\n') }
+				doc.push_str(text);
+				continue;
+			}
+
+			if !doc.is_empty() { self.docs.insert(token.span.start, doc); }
+			return token;
+		}
+	}
+
+	fn error(&mut self, error: ParseError) {
+		// one mistake (especially a missing token at the end of the file) tends to cause a few errors at the same place
+		if self.errors.last().is_some_and(|last| last.span == error.span) { return }
+		self.errors.push(error);
+	}
+
+	fn unexpected(&self, kind: ParseErrorKind) -> ParseError {
+		ParseError { kind, span: self.span() }
+	}
+
+	fn at_top_level_const_assignment(&self) -> bool {
+		matches!(self.at(), Token::Ident(_)) && *self.peek(1) == Token::ColonColon
+	}
+
+	/// Skips to the start of the next `ident ::`
+	fn recover_top_level(&mut self) {
+		while *self.at() != Token::Eof && !self.at_top_level_const_assignment() {
+			self.bump();
 		}
+	}
 
-		at
+	/// Skips past the next `;`, or up to the `}` closing the current block
+	fn recover_statement(&mut self) {
+		let mut depth = 0;
+		loop {
+			match self.at() {
+				Token::Eof => return,
+				_ if self.at_top_level_const_assignment() => return,
+
+				Token::Semicolon if depth == 0 => { self.bump(); return },
+				Token::BraceClose if depth == 0 => return,
+
+				Token::BraceOpen => depth += 1,
+				Token::BraceClose => depth -= 1,
+				_ => (),
+			}
+
+			self.bump();
+		}
+	}
+
+	// fn expect_ident(&self) -> Option<Symbol> { match self.at() {
+	// 	Token::Ident(ident) => Some(*ident),
+	// 	_ => None,
+	// } }
+
+	fn consume_ident(&mut self) -> ParseResult<Symbol> {
+		let at = self.at().ident().ok_or_else(|| self.unexpected(ParseErrorKind::ExpectedIdent(self.at().clone())))?;
+		self.bump();
+		Ok(at)
 	}
 
 	#[must_use]
 	fn consume(&mut self, token: &Token) -> Option<()> {
 		if std::mem::discriminant(self.at()) == std::mem::discriminant(token) {
-			self.pos += 1;
+			self.bump();
 			Some(())
 		} else {
 			None
 		}
 	}
 
-	// fn consume_unwrap(&mut self, token: Token) {
-	// 	self.consume(&token).expect(&format!("Expected {token:?} but found {:?}", self.at()))
-	// }
+	fn expect(&mut self, token: Token) -> ParseResult<()> {
+		self.consume(&token).ok_or_else(|| self.unexpected(ParseErrorKind::ExpectedToken { expected: token, found: self.at().clone() }))
+	}
 
-	fn parse_const_assignment(&mut self) -> ConstAssignment {
-		let ident = self.consume_ident().unwrap();
+	fn parse_const_assignment(&mut self) -> ParseResult<ConstAssignment> {
+		let start = self.span();
+		let doc = self.docs.remove(&self.span().start);
+		let ident = self.consume_ident()?;
+		self.loops.clear();
 
-		consume_unwrap!(self, Token::ColonColon);
+		self.expect(Token::ColonColon)?;
 
 		let val = match self.at() {
 			Token::Fn => {
-				self.pos += 1;
+				self.bump();
 
-				consume_unwrap!(self, Token::ParenOpen);
+				self.expect(Token::ParenOpen)?;
 
 				let mut args = Vec::new();
-				while self.pos < self.tokens.len() {
+				while *self.at() != Token::Eof {
 					if *self.at() == Token::ParenClose { break }
 
-					let name = self.consume_ident().unwrap();
+					let name = self.consume_ident()?;
 
-					consume_unwrap!(self, Token::Colon);
+					self.expect(Token::Colon)?;
 
-					let type_ = self.parse_type();
+					let type_ = self.parse_type()?;
 
-					args.push((name.clone(), type_.clone()));
+					args.push((name, type_));
 					if *self.at() == Token::ParenClose { break }
 
-					self.consume(&Token::Comma).expect("Missing comma between function parameters");
+					self.expect(Token::Comma)?;
 				}
 
-				consume_unwrap!(self, Token::ParenClose);
+				self.expect(Token::ParenClose)?;
 
 				let return_type = if *self.at() == Token::Arrow {
-					self.pos += 1;
+					self.bump();
 
-					let return_type = self.parse_type();
+					let return_type = self.parse_type()?;
 					Some(return_type)
 				} else {
 					None
 				};
 
-				consume_unwrap!(self, Token::BraceOpen);
+				self.expect(Token::BraceOpen)?;
 
 				// AA: mayhaps use parse_statement?
-				let mut body = Vec::new();
-				while *self.at() != Token::BraceClose {
-					body.push(self.parse_statement());
-				}
-
-				consume_unwrap!(self, Token::BraceClose);
+				let body = self.parse_block_body();
 
 				ConstAssignmentVal::Function { args, return_type, body }
 			},
 
 			Token::Struct => {
-				self.pos += 1;
-				consume_unwrap!(self, Token::BraceOpen);
+				self.bump();
+				self.expect(Token::BraceOpen)?;
 
 				let mut members = Vec::new();
-				while self.pos < self.tokens.len() {
+				while *self.at() != Token::Eof {
 					if *self.at() == Token::BraceClose { break }
 
-					let name = self.consume_ident().unwrap();
+					let name = self.consume_ident()?;
 
-					consume_unwrap!(self, Token::Colon);
+					self.expect(Token::Colon)?;
 
-					let type_ = self.parse_type();
+					let type_ = self.parse_type()?;
 
-					members.push((name.clone(), type_.clone()));
+					let default = match self.consume(&Token::Equals) {
+						Some(()) if self.consume(&Token::HyphenHyphenHyphen).is_some() => MemberDefault::Uninitialized,
+						Some(()) => MemberDefault::Value(self.parse_expr()?),
+						None => MemberDefault::Required,
+					};
+
+					members.push(Member { name, type_, default });
 
 					if *self.at() == Token::BraceClose { break }
-					self.consume(&Token::Comma).expect("Missing comma between struct members");
+					self.expect(Token::Comma)?;
 				}
 
-				consume_unwrap!(self, Token::BraceClose);
+				self.expect(Token::BraceClose)?;
 
 				ConstAssignmentVal::Struct(members)
 			},
 
-			_ => ConstAssignmentVal::Expression(self.parse_expr()),
+			Token::Enum => {
+				self.bump();
+				self.expect(Token::BraceOpen)?;
+
+				let mut variants = Vec::new();
+				let mut next = 0;
+				while *self.at() != Token::Eof {
+					if *self.at() == Token::BraceClose { break }
+
+					let name = self.consume_ident()?;
+
+					let value_start = self.span();
+					let value = match self.consume(&Token::Equals) {
+						Some(()) => {
+							let negative = self.consume(&Token::Hyphen).is_some();
+							let Token::NumberLiteral(n, _) = *self.at() else { return Err(self.unexpected(ParseErrorKind::ExpectedNumber(self.at().clone()))) };
+							self.bump();
+							if negative { -(n as i128) } else { n as i128 }
+						},
+						None => next,
+					};
+
+					// it's a C enum
+					if value < i32::MIN as i128 || value > i32::MAX as i128 {
+						self.error(ParseError { kind: ParseErrorKind::EnumValueOutOfRange, span: self.span_from(value_start) });
+					}
+
+					variants.push((name, value as i64));
+					next = value + 1;
+
+					if *self.at() == Token::BraceClose { break }
+					self.expect(Token::Comma)?;
+				}
+
+				self.expect(Token::BraceClose)?;
+
+				ConstAssignmentVal::Enum(variants)
+			},
+
+			Token::Union => {
+				self.bump();
+				self.expect(Token::BraceOpen)?;
+
+				let mut variants = Vec::new();
+				while *self.at() != Token::Eof {
+					if *self.at() == Token::BraceClose { break }
+
+					let name = self.consume_ident()?;
+
+					let data = match self.consume(&Token::ParenOpen) {
+						Some(()) => {
+							let type_ = self.parse_type()?;
+							self.expect(Token::ParenClose)?;
+							Some(type_)
+						},
+						None => None,
+					};
+
+					variants.push((name, data));
+
+					if *self.at() == Token::BraceClose { break }
+					self.expect(Token::Comma)?;
+				}
+
+				self.expect(Token::BraceClose)?;
+
+				ConstAssignmentVal::Union(variants)
+			},
+
+			_ => ConstAssignmentVal::Expression(self.parse_expr()?),
 		};
 
-		self.consume(&Token::Semicolon).expect("Missing ; after constant assignment");
+		let const_assignment = ConstAssignment { name: ident, val, span: self.span_from(start), doc };
+
+		// the whole thing was parsed, so keep it even without the ;
+		if let Err(e) = self.expect(Token::Semicolon) {
+			self.error(e);
+		}
 
-		ConstAssignment(ident, val)
+		Ok(const_assignment)
 	}
 
-	// FIXME: this is a bit of a hack, need to properly parse types
-	fn parse_type(&mut self) -> String {
-		let mut type_ = self.consume_ident().unwrap();
-		while *self.at() == Token::Star {
-			type_.push('*');
-			self.pos += 1;
+	fn parse_global(&mut self) -> ParseResult<Global> {
+		let start = self.span();
+		let doc = self.docs.remove(&self.span().start);
+		let name = self.consume_ident()?;
+		self.loops.clear();
+
+		self.expect(Token::Colon)?;
+		let type_ = self.parse_type()?;
+
+		let val = match self.consume(&Token::Equals) {
+			Some(()) => Some(self.parse_expr()?),
+			None => None,
+		};
+
+		let global = Global { name, type_, val, span: self.span_from(start), doc };
+
+		if let Err(e) = self.expect(Token::Semicolon) {
+			self.error(e);
 		}
 
-		type_
+		Ok(global)
 	}
 
-	fn parse_expr(&mut self) -> Expression {
-		self.parse_expr_p0()
-	}
+	/// Parses statements up to and including the closing `}`, the opening `{` should already be consumed
+	fn parse_block_body(&mut self) -> Block {
+		let mut statements = Vec::new();
+		while *self.at() != Token::BraceClose {
+			// the } is probably missing, don't eat the next top level thing
+			if *self.at() == Token::Eof || self.at_top_level_const_assignment() {
+				let e = self.unexpected(ParseErrorKind::ExpectedToken { expected: Token::BraceClose, found: self.at().clone() });
+				self.error(e);
+				return Block { statements, value: None };
+			}
 
-	fn parse_statement(&mut self) -> Statement {
-		match self.at() {
-			Token::Return => {
-				self.pos += 1;
+			match self.parse_statement() {
+				Ok(statement) => statements.push(statement),
+				Err(e) => {
+					self.error(e);
+					self.recover_statement();
+				},
+			}
+		}
 
-				let expr = self.parse_expr();
-				consume_unwrap!(self, Token::Semicolon);
+		self.bump();
 
-				Statement::Return(expr)
+		// an expression statement that ends where its expression ends didn't have a ;
+		let value = match statements.last() {
+			Some(Statement { kind: StatementKind::Expression(expr), span }) if expr.span.end == span.end => {
+				let Some(Statement { kind: StatementKind::Expression(expr), .. }) = statements.pop() else { unreachable!() };
+				Some(Box::new(expr))
 			},
+			_ => None,
+		};
 
-			Token::Let => {
-				self.pos += 1;
+		Block { statements, value }
+	}
+
+	/// `if`, loops and blocks
+	fn parse_block_like_expr(&mut self) -> ParseResult<Expression> {
+		let start = self.span();
+		let kind = match self.at() {
+			Token::If => {
+				self.bump();
 
-				let name = self.consume_ident().unwrap();
-				self.consume(&Token::Colon).expect("No explicit type hint, type inference isn't implemented (yet)");
-				
-				let type_ = self.parse_type();
+				self.expect(Token::ParenOpen)?;
+				let cond = Box::new(self.parse_expr()?);
+				self.expect(Token::ParenClose)?;
 
-				let val = if *self.at() == Token::Equals {
-					self.pos += 1;
-					Some(self.parse_expr())
-				} else {
-					None
+				let body = Box::new(self.parse_branch()?);
+
+				// an else goes with the closest if, like in C
+				let else_body = match self.consume(&Token::Else) {
+					Some(()) => Some(Box::new(self.parse_branch()?)),
+					None => None,
 				};
-				
-				consume_unwrap!(self, Token::Semicolon);
 
-				Statement::Let(name, type_, val)
+				ExpressionKind::If { cond, body, else_body }
 			},
 
-			Token::If => {
-				self.pos += 1;
+			Token::While | Token::For => return self.parse_loop(None),
 
-				consume_unwrap!(self, Token::ParenOpen);
-				let cond = self.parse_expr();
-				consume_unwrap!(self, Token::ParenClose);
+			Token::Switch => {
+				self.bump();
 
-				let body = self.parse_statement();
+				self.expect(Token::ParenOpen)?;
+				let value = Box::new(self.parse_expr()?);
+				self.expect(Token::ParenClose)?;
+
+				self.expect(Token::BraceOpen)?;
+				let mut arms: Vec<SwitchArm> = Vec::new();
+				while *self.at() != Token::BraceClose {
+					let arm_start = self.span();
+					let arm = self.parse_switch_arm()?;
+					if arm.variants.is_empty() && arms.iter().any(|arm| arm.variants.is_empty()) {
+						self.error(ParseError { kind: ParseErrorKind::DuplicateElse, span: self.span_from(arm_start) });
+					}
+
+					arms.push(arm);
+				}
+				self.bump();
 
-				Statement::If(cond, Box::new(body))
+				ExpressionKind::Switch { value, arms, type_: None }
 			},
 
+			_ => {
+				self.expect(Token::BraceOpen)?;
+				ExpressionKind::Block(self.parse_block_body())
+			},
+		};
+
+		Ok(Expression { kind, span: self.span_from(start) })
+	}
+
+	/// `while` and `for`, `label` was already consumed
+	fn parse_loop(&mut self, label: Option<Symbol>) -> ParseResult<Expression> {
+		let start = self.span();
+		let kind = match self.at() {
 			Token::While => {
-				self.pos += 1;
+				self.bump();
 
-				consume_unwrap!(self, Token::ParenOpen);
-				let cond = self.parse_expr();
-				consume_unwrap!(self, Token::ParenClose);
+				self.expect(Token::ParenOpen)?;
+				let cond = Box::new(self.parse_expr()?);
+				self.expect(Token::ParenClose)?;
 
-				let body = self.parse_statement();
+				let body = Box::new(self.parse_loop_body(label)?);
 
-				Statement::While(cond, Box::new(body))
+				ExpressionKind::While { cond, body, label }
 			},
 
-			Token::BraceOpen => {
-				self.pos += 1;
+			Token::For if *self.peek(1) == Token::ParenOpen => {
+				self.bump();
+				self.bump();
 
-				let mut body = Vec::new();
-				while *self.at() != Token::BraceClose {
-					body.push(self.parse_statement());
+				let init = match self.consume(&Token::Semicolon) {
+					Some(()) => None,
+					// takes the ; too
+					None => Some(Box::new(self.parse_statement()?)),
+				};
+
+				let cond = if *self.at() == Token::Semicolon { None } else { Some(Box::new(self.parse_expr()?)) };
+				self.expect(Token::Semicolon)?;
+
+				let step = if *self.at() == Token::ParenClose { None } else { Some(Box::new(self.parse_expr()?)) };
+				self.expect(Token::ParenClose)?;
+
+				let body = Box::new(self.parse_loop_body(label)?);
+
+				ExpressionKind::For { init, cond, step, body, label }
+			},
+
+			Token::For => {
+				self.bump();
+
+				let var = self.consume_ident()?;
+				self.expect(Token::In)?;
+
+				let outer = std::mem::replace(&mut self.no_struct_literal, true);
+				let range = self.parse_expr().and_then(|start| {
+					self.expect(Token::PeriodPeriod)?;
+					Ok((start, self.parse_expr()?))
+				});
+				self.no_struct_literal = outer;
+
+				let (start, end) = range?;
+				let (start, end) = (Box::new(start), Box::new(end));
+
+				let body = Box::new(self.parse_loop_body(label)?);
+
+				ExpressionKind::ForIn { var, start, end, body, label, type_: None }
+			},
+
+			t => return Err(self.unexpected(ParseErrorKind::ExpectedLoop(t.clone()))),
+		};
+
+		Ok(Expression { kind, span: self.span_from(start) })
+	}
+
+	/// `Red | Green => body,`, `Circle(r) => body,` or `else => body,`. The , is optional after a block
+	fn parse_switch_arm(&mut self) -> ParseResult<SwitchArm> {
+		let arm_start = self.span();
+		let mut variants = Vec::new();
+		let mut binding = None;
+		if self.consume(&Token::Else).is_none() {
+			loop {
+				let start = self.span();
+				let variant = self.consume_ident()?;
+				variants.push((variant, self.span_from(start)));
+
+				if self.consume(&Token::ParenOpen).is_some() {
+					binding = Some(self.consume_ident()?);
+					self.expect(Token::ParenClose)?;
 				}
 
-				consume_unwrap!(self, Token::BraceClose);
+				if self.consume(&Token::Pipe).is_none() { break }
+			}
+		}
+
+		// the variants could have different data, or none
+		if binding.is_some() && variants.len() > 1 {
+			let span = self.span_from(arm_start);
+			self.error(ParseError { kind: ParseErrorKind::BindingWithAlternatives, span });
+		}
+
+		self.expect(Token::FatArrow)?;
+		let body = self.parse_expr()?;
+
+		if *self.at() != Token::BraceClose {
+			if body.is_block_like() {
+				let _ = self.consume(&Token::Comma);
+			} else {
+				self.expect(Token::Comma)?;
+			}
+		}
+
+		Ok(SwitchArm { variants, binding, body })
+	}
+
+	fn parse_loop_body(&mut self, label: Option<Symbol>) -> ParseResult<Expression> {
+		self.loops.push(label);
+		let body = self.parse_branch();
+		self.loops.pop();
+		body
+	}
 
-				Statement::Compound(body)
+	/// The body of an `if`, `else` or loop, a block or a single statement (which is like a block with just it).
+	/// The ; after an `else if` belongs to whatever the outer if is in
+	fn parse_branch(&mut self) -> ParseResult<Expression> {
+		if matches!(self.at(), Token::BraceOpen | Token::If | Token::While | Token::For | Token::Switch) { return self.parse_block_like_expr() }
+
+		let statement = self.parse_statement()?;
+		let span = statement.span;
+		Ok(Expression { kind: ExpressionKind::Block(Block { statements: vec![statement], value: None }), span })
+	}
+
+	fn parse_type(&mut self) -> ParseResult<Type> {
+		let mut type_ = match self.at() {
+			Token::Fn => {
+				self.bump();
+				self.expect(Token::ParenOpen)?;
+				let mut args = Vec::new();
+				while *self.at() != Token::ParenClose {
+					args.push(self.parse_type()?);
+
+					if *self.at() != Token::Comma { break }
+					self.bump();
+				}
+
+				self.expect(Token::ParenClose)?;
+
+				// the return type takes the * and [] after it, (fn() -> int)* is a pointer to the function pointer
+				let return_type = match self.consume(&Token::Arrow) {
+					Some(()) => Some(Box::new(self.parse_type()?)),
+					None => None,
+				};
+
+				Type::Function { args, return_type }
+			},
+
+			Token::ParenOpen => {
+				self.bump();
+				let type_ = self.parse_type()?;
+				self.expect(Token::ParenClose)?;
+				type_
 			},
 
 			_ => {
-				let expr = self.parse_expr();
-				consume_unwrap!(self, Token::Semicolon);
+				let start = self.span();
+				let name = self.consume_ident()?;
+				if self.consume(&Token::AngleBracketOpen).is_some() {
+					let mut args = vec![self.parse_type()?];
+					while self.consume(&Token::Comma).is_some() {
+						args.push(self.parse_type()?);
+					}
+
+					self.expect_angle_bracket_close()?;
+					let type_ = Type::Generic(name, args);
+					self.error(ParseError { kind: ParseErrorKind::GenericType(type_.clone()), span: self.span_from(start) });
+					type_
+				} else {
+					Type::Named(name)
+				}
+			},
+		};
+
+		loop {
+			match *self.at() {
+				Token::Star => {
+					self.bump();
+					type_ = Type::Pointer(Box::new(type_));
+				},
+
+				Token::BracketOpen => {
+					self.bump();
+					if let Token::NumberLiteral(n, _) = *self.at() {
+						self.bump();
+						type_ = Type::Array(Box::new(type_), n);
+					} else {
+						type_ = Type::Slice(Box::new(type_));
+					}
+
+					self.expect(Token::BracketClose)?;
+				},
+
+				_ => return Ok(type_),
+			}
+		}
+	}
+
+	/// `List<List<int>>` ends with a `>>` token, this takes just the first `>` off of it (or off `>=` and `>>=`)
+	fn expect_angle_bracket_close(&mut self) -> ParseResult<()> {
+		let rest = match self.at() {
+			Token::AngleBracketCloseClose => Token::AngleBracketClose,
+			Token::AngleBracketCloseEquals => Token::Equals,
+			Token::AngleBracketCloseCloseEquals => Token::AngleBracketCloseEquals,
+			_ => return self.expect(Token::AngleBracketClose),
+		};
 
-				Statement::Expression(expr)
+		let token = &mut self.lookahead[0];
+		self.prev_span = Span { end: token.span.start + 1, ..token.span };
+		token.token = rest;
+		token.span.start += 1;
+		token.span.col += 1;
+		Ok(())
+	}
+
+	fn parse_expr(&mut self) -> ParseResult<Expression> {
+		self.parse_expr_p0()
+	}
+
+	fn parse_statement(&mut self) -> ParseResult<Statement> {
+		let start = self.span();
+		let kind = match self.at() {
+			Token::Return => {
+				self.bump();
+
+				let expr = self.parse_expr()?;
+				self.expect(Token::Semicolon)?;
+
+				StatementKind::Return(expr)
+			},
+
+			Token::Let => {
+				self.bump();
+
+				let name_span = self.span();
+				let name = self.consume_ident()?;
+
+				let type_ = match self.consume(&Token::Colon) {
+					Some(()) => Some(self.parse_type()?),
+					None => None,
+				};
+
+				let (val, uninitialized) = match self.consume(&Token::Equals) {
+					Some(()) if self.consume(&Token::HyphenHyphenHyphen).is_some() => (None, true),
+					Some(()) => (Some(self.parse_expr()?), false),
+					None => (None, false),
+				};
+
+				// there's nothing to infer the type from
+				if type_.is_none() && val.is_none() {
+					self.error(ParseError { kind: ParseErrorKind::MissingTypeAnnotation, span: name_span });
+				}
+
+				self.expect(Token::Semicolon)?;
+
+				StatementKind::Let { name, name_span, type_, val, uninitialized }
+			},
+
+			Token::Break | Token::Continue => {
+				let token = self.at().clone();
+				self.bump();
+
+				let label = match *self.at() {
+					Token::Ident(label) => { self.bump(); Some(label) },
+					_ => None,
+				};
+
+				if self.loops.is_empty() {
+					self.error(ParseError { kind: ParseErrorKind::OutsideOfLoop(token.clone()), span: self.span_from(start) });
+				} else if let Some(label) = label.filter(|&label| !self.loops.contains(&Some(label))) {
+					self.error(ParseError { kind: ParseErrorKind::UnknownLabel(label), span: self.span_from(start) });
+				}
+
+				self.expect(Token::Semicolon)?;
+
+				if token == Token::Break { StatementKind::Break(label) } else { StatementKind::Continue(label) }
+			},
+
+			// outer: while (...) {}
+			Token::Ident(label) if *self.peek(1) == Token::Colon => {
+				let label = *label;
+				self.bump();
+				self.bump();
+
+				let expr = self.parse_loop(Some(label))?;
+				let _ = self.consume(&Token::Semicolon);
+
+				StatementKind::Expression(expr)
+			},
+
+			// these end the statement without a ;, so `if (c) {} *p = 1;` isn't a multiplication
+			Token::If | Token::While | Token::For | Token::Switch | Token::BraceOpen => {
+				let expr = self.parse_block_like_expr()?;
+				let _ = self.consume(&Token::Semicolon);
+
+				StatementKind::Expression(expr)
+			},
+
+			_ => {
+				let expr = self.parse_expr()?;
+				// unless it's the value at the end of a block
+				if *self.at() != Token::BraceClose {
+					self.expect(Token::Semicolon)?;
+				}
+
+				StatementKind::Expression(expr)
 			},
 
 			// ref t => panic!("Unexpected token while parsing statement: {t:?}"),
-		}
+		};
+
+		Ok(Statement { kind, span: self.span_from(start) })
 	}
 }
 
 macro_rules! parse_expr_pn {
 	($name:ident, $higher_name:ident, $( $pattern:pat_param )|+) => {
-		fn $name(&mut self) -> Expression {
-			let mut left = self.$higher_name();
+		fn $name(&mut self) -> ParseResult<Expression> {
+			let start = self.span();
+			let mut left = self.$higher_name()?;
 
 			while matches!(self.at(), $($pattern)|+) {
-				let op = Operator::to_binary_op(self.at()).expect(&format!("Could not convert {:?} into a binary operator", self.at()));
-				self.pos += 1;
+				let op = Operator::to_binary_op(self.at()).unwrap_or_else(|| panic!("Could not convert {:?} into a binary operator", self.at()));
+				self.bump();
 
-				let right = self.$higher_name();
-				left = Expression::BinaryOperator { op, left: Box::new(left), right: Box::new(right) };
+				let right = self.$higher_name()?;
+				left = Expression {
+					kind: ExpressionKind::BinaryOperator { op, left: Box::new(left), right: Box::new(right) },
+					span: self.span_from(start),
+				};
 			}
 
-			return left;
+			return Ok(left);
 		}
 	};
 }
 
-impl<'a> Parser<'a> {
-	// FIXME: this is supposed to be rtl
-	parse_expr_pn!(parse_expr_p0, parse_expr_p1, Token::Equals); // , +=, ...
+impl<I: Iterator<Item = SpannedToken>> Parser<I> {
+	// assignment is right to left, a = b = c is a = (b = c)
+	fn parse_expr_p0(&mut self) -> ParseResult<Expression> {
+		let start = self.span();
+		let left = self.parse_expr_p1()?;
+
+		if !matches!(self.at(),
+			Token::Equals | Token::PlusEquals | Token::HyphenEquals | Token::StarEquals | Token::SlashEquals | Token::PercentEquals |
+			Token::AmpersandEquals | Token::PipeEquals | Token::CaretEquals | Token::AngleBracketOpenOpenEquals | Token::AngleBracketCloseCloseEquals
+		) {
+			return Ok(left);
+		}
+
+		let op = Operator::to_binary_op(self.at()).unwrap_or_else(|| panic!("Could not convert {:?} into a binary operator", self.at()));
+		self.bump();
+
+		// the rest of the expression is fine, so just report it and keep going
+		if !left.is_lvalue() {
+			self.error(ParseError { kind: ParseErrorKind::InvalidAssignmentTarget, span: left.span });
+		}
+
+		let right = self.parse_expr_p0()?;
+		Ok(Expression {
+			kind: ExpressionKind::BinaryOperator { op, left: Box::new(left), right: Box::new(right) },
+			span: self.span_from(start),
+		})
+	}
 
-	parse_expr_pn!(parse_expr_p1, parse_expr_p2, Token::FIXME_DELETE(_)); // ||
-	parse_expr_pn!(parse_expr_p2, parse_expr_p3, Token::FIXME_DELETE(_)); // &&
-	parse_expr_pn!(parse_expr_p3, parse_expr_p4, Token::FIXME_DELETE(_)); // |
-	parse_expr_pn!(parse_expr_p4, parse_expr_p5, Token::FIXME_DELETE(_)); // ^
-	parse_expr_pn!(parse_expr_p5, parse_expr_p6, Token::FIXME_DELETE(_)); // &
+	parse_expr_pn!(parse_expr_p1, parse_expr_p2, Token::PipePipe);
+	parse_expr_pn!(parse_expr_p2, parse_expr_p3, Token::AmpersandAmpersand);
+	parse_expr_pn!(parse_expr_p3, parse_expr_p4, Token::Pipe);
+	parse_expr_pn!(parse_expr_p4, parse_expr_p5, Token::Caret);
+	parse_expr_pn!(parse_expr_p5, parse_expr_p6, Token::Ampersand);
 
-	parse_expr_pn!(parse_expr_p6, parse_expr_p7, Token::EqualsEquals); // , !=
-	parse_expr_pn!(parse_expr_p7, parse_expr_p8, Token::AngleBracketOpen | Token::AngleBracketClose); // <= >=
+	parse_expr_pn!(parse_expr_p6, parse_expr_p7, Token::EqualsEquals | Token::ExclamationEquals);
+	parse_expr_pn!(parse_expr_p7, parse_expr_p8, Token::AngleBracketOpen | Token::AngleBracketClose | Token::AngleBracketOpenEquals | Token::AngleBracketCloseEquals);
 
-	parse_expr_pn!(parse_expr_p8, parse_expr_p9, Token::FIXME_DELETE(_)); // << >>
+	parse_expr_pn!(parse_expr_p8, parse_expr_p9, Token::AngleBracketOpenOpen | Token::AngleBracketCloseClose);
 
 	parse_expr_pn!(parse_expr_p9, parse_expr_p10, Token::Plus | Token::Hyphen);
-	parse_expr_pn!(parse_expr_p10, parse_unary_rtl, Token::Star);
+	parse_expr_pn!(parse_expr_p10, parse_unary_rtl, Token::Star | Token::Slash | Token::Percent);
 
-	parse_expr_pn!(parse_expr_p11, parse_primary_expr, Token::Period);
 
-	fn parse_unary_rtl(&mut self) -> Expression {
-		if matches!(self.at(), Token::Star | Token::Plus) {
-			let op = Operator::to_unary_op(self.at()).expect(&format!("Could not convert {:?} into a unary operator", self.at()));
-			self.pos += 1;
+	fn parse_unary_rtl(&mut self) -> ParseResult<Expression> {
+		if matches!(self.at(), Token::Star | Token::Plus | Token::Hyphen | Token::Ampersand | Token::Exclamation | Token::Tilde) {
+			let start = self.span();
+			let op = Operator::to_unary_op(self.at()).unwrap_or_else(|| panic!("Could not convert {:?} into a unary operator", self.at()));
+			self.bump();
 
 			// AA: should this be a parse_unary_rtl or parse_expr?
-			return Expression::UnaryOperator { op, operand: Box::new(self.parse_unary_rtl()) };
+			let operand = Box::new(self.parse_unary_rtl()?);
+			if let Operator::AddressOf = op {
+				if !operand.is_lvalue() { self.error(ParseError { kind: ParseErrorKind::InvalidAddressOf, span: operand.span }) }
+			}
+
+			return Ok(Expression { kind: ExpressionKind::UnaryOperator { op, operand }, span: self.span_from(start) });
 		}
 
-		self.parse_expr_p11()
+		self.parse_postfix()
+	}
+
+	// member access and calls, left to right
+	fn parse_postfix(&mut self) -> ParseResult<Expression> {
+		let start = self.span();
+		let mut expr = self.parse_primary_expr()?;
+
+		loop {
+			let kind = match self.at() {
+				Token::Period => {
+					self.bump();
+					let member_start = self.span();
+					let member = self.consume_ident()?;
+					let member = Expression { kind: ExpressionKind::Ident(member), span: self.span_from(member_start) };
+					ExpressionKind::BinaryOperator { op: Operator::MemberAccess, left: Box::new(expr), right: Box::new(member) }
+				},
+
+				Token::ParenOpen => {
+					self.bump();
+					let outer = std::mem::replace(&mut self.no_struct_literal, false);
+					let args = self.parse_call_args();
+					self.no_struct_literal = outer;
+
+					ExpressionKind::FunctionCall(Box::new(expr), args?)
+				},
+
+				_ => return Ok(expr),
+			};
+
+			expr = Expression { kind, span: self.span_from(start) };
+		}
 	}
 
-	fn parse_primary_expr(&mut self) -> Expression {
-		match self.tokens[self.pos] {
-			Token::NumberLiteral(n) => { self.pos += 1; Expression::NumberLiteral(n) },
-			Token::StringLiteral(ref s) => { self.pos += 1; Expression::StringLiteral(s.clone()) },
+	fn parse_primary_expr(&mut self) -> ParseResult<Expression> {
+		let start = self.span();
+		let kind = match *self.at() {
+			Token::NumberLiteral(n, suffix) => { self.bump(); ExpressionKind::NumberLiteral(n, suffix) },
+			Token::FloatLiteral(n, suffix) => { self.bump(); ExpressionKind::FloatLiteral(n, suffix) },
+			Token::StringLiteral(ref s) => { let s = s.clone(); self.bump(); ExpressionKind::StringLiteral(s) },
+			Token::CharLiteral(c) => { self.bump(); ExpressionKind::CharLiteral(c) },
 
 			Token::ParenOpen => {
-				self.pos += 1;
+				self.bump();
+				let outer = std::mem::replace(&mut self.no_struct_literal, false);
 				let expr = self.parse_expr();
-				consume_unwrap!(self, Token::ParenClose);
+				self.no_struct_literal = outer;
+
+				let expr = expr?;
+				self.expect(Token::ParenClose)?;
 
-				expr
+				// keep the parens in the span
+				return Ok(Expression { kind: expr.kind, span: self.span_from(start) });
 			},
 
-			// TODO: this needs to be an actual operator
-			Token::Ident(ref ident) if self.tokens[self.pos + 1] == Token::ParenOpen => {
-				self.pos += 2; // ident + (
-				let mut args = Vec::new();
-				while *self.at() != Token::ParenClose {
-					args.push(self.parse_expr());
+			Token::Ident(name) if *self.peek(1) == Token::BraceOpen && !self.no_struct_literal => {
+				self.bump();
+				self.bump();
 
-					if *self.at() != Token::Comma { break }
-					self.pos += 1;
-				}
-
-				consume_unwrap!(self, Token::ParenClose);
+				let outer = std::mem::replace(&mut self.no_struct_literal, false);
+				let members = self.parse_struct_literal_members();
+				self.no_struct_literal = outer;
 
-				Expression::FunctionCall(ident.clone(), args)
+				ExpressionKind::StructLiteral { name, members: members? }
 			},
 
-			Token::Ident(ref ident) => { self.pos += 1; Expression::Ident(ident.clone()) },
+			Token::Ident(ident) => { self.bump(); ExpressionKind::Ident(ident) },
+
+			Token::If | Token::While | Token::For | Token::Switch | Token::BraceOpen => return self.parse_block_like_expr(),
+
+			ref t => return Err(self.unexpected(ParseErrorKind::ExpectedExpression(t.clone()))),
+		};
+
+		Ok(Expression { kind, span: self.span_from(start) })
+	}
+}
+
+impl<I: Iterator<Item = SpannedToken>> Parser<I> {
+	/// `a, b)`, the `(` should already be consumed
+	fn parse_call_args(&mut self) -> ParseResult<Vec<Expression>> {
+		let mut args = Vec::new();
+		while *self.at() != Token::ParenClose {
+			args.push(self.parse_expr()?);
+
+			if *self.at() != Token::Comma { break }
+			self.bump();
+		}
+
+		self.expect(Token::ParenClose)?;
+		Ok(args)
+	}
 
-			ref t => panic!("Unexpected token while parsing expression: {t:?}"),
+	/// `x = 1, y = 2 }` or `1, 2 }`, the `{` should already be consumed
+	fn parse_struct_literal_members(&mut self) -> ParseResult<Vec<(Option<Symbol>, Expression)>> {
+		let mut members: Vec<(Option<Symbol>, Expression)> = Vec::new();
+		while *self.at() != Token::BraceClose {
+			let name = match (self.at(), self.peek(1)) {
+				(&Token::Ident(name), Token::Equals) => {
+					self.bump();
+					self.bump();
+					Some(name)
+				},
+				_ => None,
+			};
+
+			let val = self.parse_expr()?;
+			if members.first().is_some_and(|(first, _)| first.is_some() != name.is_some()) {
+				self.error(ParseError { kind: ParseErrorKind::MixedStructLiteral, span: val.span });
+			}
+
+			members.push((name, val));
+			if *self.at() != Token::Comma { break }
+			self.bump();
 		}
+
+		self.expect(Token::BraceClose)?;
+		Ok(members)
 	}
 }
 
 // fn is_ident(token: &Token) -> bool { matches!(token, Token::Ident(_)) }
-// fn expect_ident(token: &Token) -> Option<&String> { match token { Token::Ident(s) => Some(s), _ => None } }
+// fn expect_ident(token: &Token) -> Option<Symbol> { match token { Token::Ident(s) => Some(*s), _ => None } }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::lexer::lex;
+	use crate::span::FileId;
+
+	fn parse_str(input: &str) -> (AstRoot, Vec<ParseError>) {
+		let (tokens, errors) = lex(input, FileId(0));
+		assert!(errors.is_empty());
+		parse(tokens)
+	}
+
+	fn position(error: &ParseError) -> (u32, u32) { (error.span.line, error.span.col) }
+
+	#[test]
+	fn recovers_after_errors() {
+		let (root, errors) = parse_str(concat!(
+			"count: int = 3;\n",
+			"a :: fn() -> int {\n",
+			"\tlet x = ;\n", // skips to the ;
+			"\treturn 1;\n",
+			"};\n",
+			"b :: 1 + ;\n", // skips to the next ::
+			"c :: fn() { x = ) };\n", // skips to the }
+			"d :: 5;\n",
+		));
+
+		let errors = errors.iter().map(|e| (e.kind.clone(), position(e))).collect::<Vec<_>>();
+		assert_eq!(errors, [
+			(ParseErrorKind::ExpectedExpression(Token::Semicolon), (3, 10)),
+			(ParseErrorKind::ExpectedExpression(Token::Semicolon), (6, 10)),
+			(ParseErrorKind::ExpectedExpression(Token::ParenClose), (7, 17)),
+		]);
+
+		let names = root.consts.iter().map(|c| c.name.to_string()).collect::<Vec<_>>();
+		assert_eq!(names, ["a", "c", "d"]);
+		assert_eq!(root.globals.len(), 1);
+		assert_eq!(root.globals[0].name.to_string(), "count");
+
+		// the statement after the bad one is still there
+		let ConstAssignmentVal::Function { body, .. } = &root.consts[0].val else { panic!("a isn't a function") };
+		assert!(matches!(body.statements[..], [Statement { kind: StatementKind::Return(_), .. }]));
+		let ConstAssignmentVal::Function { body, .. } = &root.consts[1].val else { panic!("c isn't a function") };
+		assert!(body.statements.is_empty());
+		assert!(matches!(root.consts[2].val, ConstAssignmentVal::Expression(Expression { kind: ExpressionKind::NumberLiteral(5, None), .. })));
+	}
+
+	#[test]
+	fn doc_comments() {
+		let (root, errors) = parse_str(concat!(
+			"/// Adds one\n",
+			"/// to x\n",
+			"inc :: fn(x: int) -> int {\n",
+			"\t/// not on anything\n",
+			"\treturn x + 1;\n",
+			"};\n",
+			"//// just a comment\n",
+			"ZERO :: 0;\n",
+			"/// How many\n",
+			"count: int = 0;\n",
+		));
+		assert!(errors.is_empty());
+
+		assert_eq!(root.consts[0].doc.as_deref(), Some("Adds one\nto x"));
+		assert_eq!(root.consts[1].doc, None);
+		assert_eq!(root.globals[0].doc.as_deref(), Some("How many"));
+	}
+}
@@ -0,0 +1,19 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FileId(pub u32);
+
+/// `start` and `end` are byte offsets into the file, `line` and `col` are 1-based and point at `start`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+	pub file: FileId,
+	pub start: usize,
+	pub end: usize,
+	pub line: u32,
+	pub col: u32,
+}
+
+impl Span {
+	/// From the start of `self` to the end of `other`
+	pub const fn to(self, other: Span) -> Span {
+		Span { end: other.end, ..self }
+	}
+}
@@ -0,0 +1,48 @@
+use std::collections::HashMap;
+use std::sync::{ LazyLock, Mutex };
+
+/// An interned string, comparing and copying it is as cheap as a u32
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+#[derive(Default)]
+struct Interner {
+	symbols: HashMap<&'static str, Symbol>,
+	strings: Vec<&'static str>,
+}
+
+// the strings are leaked so `as_str` can give out &'static str. only unique strings are kept, the compiler doesn't
+// have many of them and exits when it's done. the lsp never frees any though: every identifier it has ever seen
+// stays, half typed ones too (f, fo, foo), so its memory slowly grows over a long session
+static INTERNER: LazyLock<Mutex<Interner>> = LazyLock::new(Default::default);
+
+impl Symbol {
+	pub fn intern(s: &str) -> Symbol {
+		let mut interner = INTERNER.lock().unwrap();
+		if let Some(&symbol) = interner.symbols.get(s) {
+			return symbol;
+		}
+
+		let s: &'static str = Box::leak(s.into());
+		let symbol = Symbol(interner.strings.len() as u32);
+		interner.strings.push(s);
+		interner.symbols.insert(s, symbol);
+		symbol
+	}
+
+	pub fn as_str(self) -> &'static str {
+		INTERNER.lock().unwrap().strings[self.0 as usize]
+	}
+}
+
+impl std::fmt::Display for Symbol {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str(self.as_str())
+	}
+}
+
+impl std::fmt::Debug for Symbol {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{:?}", self.as_str())
+	}
+}
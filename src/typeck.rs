@@ -0,0 +1,689 @@
+use crate::span::Span;
+use crate::symbol::Symbol;
+use crate::lexer::{ IntSuffix, FloatSuffix };
+use crate::consteval::Value;
+use crate::parser::{ AstRoot, ConstAssignmentVal, MemberDefault, Type, Block, Statement, StatementKind, Expression, ExpressionKind, Operator };
+use std::collections::HashMap;
+
+/// A `let` without a type annotation and the type it got, for the lsp's inlay hints
+#[derive(Debug, Clone, PartialEq)]
+pub struct Inferred {
+	/// Of the name, the hint goes after it
+	pub span: Span,
+	pub type_: Type,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeErrorKind {
+	CantInfer(Symbol),
+	NotCallable(Type),
+	ArgumentCount { expected: usize, found: usize },
+	NotAPointer(Type),
+	InvalidPointerArithmetic(Type, Type),
+	NoMembers(Type),
+	UnknownMember { type_: Type, member: Symbol },
+	UnknownStruct(Symbol),
+	DuplicateMember(Symbol),
+	MissingMembers { type_: Type, members: Vec<Symbol> },
+	TooManyMembers { expected: usize, found: usize },
+	DuplicateVariant(Symbol),
+	/// Two variants of an enum, a switch couldn't tell them apart
+	SameValue(Symbol, Symbol),
+	UnknownVariant { type_: Type, variant: Symbol },
+	MissingData(Symbol),
+	NoData(Symbol),
+	NotSwitchable(Type),
+	DuplicateArm(Symbol),
+	NotExhaustive { type_: Type, missing: Vec<Symbol> },
+	/// A loop, or a block or an `if` that doesn't end with a value, used as a value
+	NoValue,
+	/// The start and the end of a `for in`
+	InvalidRange(Type, Type),
+	/// An `if` without an `else` that's used as a value
+	MissingElse,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeError {
+	pub kind: TypeErrorKind,
+	pub span: Span,
+}
+
+impl std::fmt::Display for TypeError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match &self.kind {
+			TypeErrorKind::CantInfer(name) => write!(f, "can't infer the type of '{name}', it needs a type annotation"),
+			TypeErrorKind::NotCallable(type_) => write!(f, "can't call something of type '{type_}'"),
+			TypeErrorKind::NotAPointer(type_) => write!(f, "can't dereference something of type '{type_}'"),
+			TypeErrorKind::InvalidPointerArithmetic(left, right) => write!(f, "invalid pointer arithmetic between '{left}' and '{right}'"),
+			TypeErrorKind::NoMembers(type_) => write!(f, "'{type_}' isn't a struct, it doesn't have members"),
+			TypeErrorKind::UnknownMember { type_, member } => write!(f, "'{type_}' doesn't have a member '{member}'"),
+			TypeErrorKind::UnknownStruct(name) => write!(f, "there's no struct '{name}'"),
+			TypeErrorKind::DuplicateMember(member) => write!(f, "'{member}' is given more than once"),
+			TypeErrorKind::MissingMembers { type_, members } => {
+				let members = members.iter().map(|member| format!("'{member}'")).collect::<Vec<_>>().join(", ");
+				write!(f, "missing {members} for '{type_}'")
+			},
+			TypeErrorKind::TooManyMembers { expected, found } => write!(f, "expected {expected} member{}, found {found}", if *expected == 1 { "" } else { "s" }),
+			TypeErrorKind::DuplicateVariant(variant) => write!(f, "there's already a variant '{variant}'"),
+			TypeErrorKind::SameValue(variant, other) => write!(f, "'{variant}' has the same value as '{other}'"),
+			TypeErrorKind::UnknownVariant { type_, variant } => write!(f, "'{type_}' doesn't have a variant '{variant}'"),
+			TypeErrorKind::MissingData(variant) => write!(f, "'{variant}' needs its data, like '{variant}(data)'"),
+			TypeErrorKind::NoData(variant) => write!(f, "'{variant}' doesn't have any data"),
+			TypeErrorKind::NotSwitchable(type_) => write!(f, "can only switch on an enum or a union, not '{type_}'"),
+			TypeErrorKind::DuplicateArm(variant) => write!(f, "'{variant}' is already covered"),
+			TypeErrorKind::NotExhaustive { type_, missing } => {
+				let missing = missing.iter().map(|variant| format!("'{variant}'")).collect::<Vec<_>>().join(", ");
+				write!(f, "the switch doesn't cover {missing} of '{type_}'")
+			},
+			TypeErrorKind::InvalidRange(start, end) => write!(f, "can't loop from '{start}' to '{end}'"),
+			TypeErrorKind::MissingElse => write!(f, "this if is used as a value, it needs an else for when the condition is false"),
+			TypeErrorKind::NoValue => write!(f, "this doesn't have a value, it can't be used as one"),
+			TypeErrorKind::ArgumentCount { expected, found } => write!(f, "expected {expected} argument{}, found {found}", if *expected == 1 { "" } else { "s" }),
+		}
+	}
+}
+
+/// Gives every `let` without a type the type of its value, so after this (without errors) they all have one.
+/// Types follow C's rules since that's what the code ends up as, constants have the type of their value
+pub fn check(ast: &mut AstRoot, consts: &HashMap<Symbol, Value>) -> (Vec<Inferred>, Vec<TypeError>) {
+	let mut checker = Checker {
+		globals: consts.iter().map(|(name, value)| (*name, value.type_())).collect(),
+		..Default::default()
+	};
+	checker.globals.extend(ast.globals.iter().map(|global| (global.name, global.type_.clone())));
+
+	for ca in ast.consts.iter() {
+		match &ca.val {
+			ConstAssignmentVal::Function { args, return_type, .. } => {
+				let type_ = Type::Function { args: args.iter().map(|(_, type_)| type_.clone()).collect(), return_type: return_type.clone().map(Box::new) };
+				checker.functions.insert(ca.name, type_);
+			},
+			ConstAssignmentVal::Struct(members) => {
+				let members = members.iter().map(|member| (member.name, member.type_.clone(), !matches!(member.default, MemberDefault::Required))).collect();
+				checker.structs.insert(ca.name, members);
+			},
+			ConstAssignmentVal::Enum(variants) => {
+				for (i, (variant, value)) in variants.iter().enumerate() {
+					if let Some((other, _)) = variants[..i].iter().find(|(other, other_value)| other == variant || other_value == value) {
+						let kind = if other == variant { TypeErrorKind::DuplicateVariant(*variant) } else { TypeErrorKind::SameValue(*variant, *other) };
+						checker.errors.push(TypeError { kind, span: ca.span });
+					}
+				}
+
+				checker.variants.insert(ca.name, variants.iter().map(|(variant, _)| (*variant, None)).collect());
+			},
+			ConstAssignmentVal::Union(variants) => {
+				for (i, (variant, _)) in variants.iter().enumerate() {
+					if variants[..i].iter().any(|(other, _)| other == variant) {
+						checker.errors.push(TypeError { kind: TypeErrorKind::DuplicateVariant(*variant), span: ca.span });
+					}
+				}
+
+				checker.variants.insert(ca.name, variants.clone());
+			},
+			ConstAssignmentVal::Expression(_) => (),
+		}
+	}
+
+	for global in ast.globals.iter_mut() {
+		if let Some(val) = &mut global.val {
+			checker.locals.clear();
+			checker.check_expression(val);
+		}
+	}
+
+	for ca in ast.consts.iter_mut() {
+		match &mut ca.val {
+			ConstAssignmentVal::Function { args, return_type, body } => {
+				checker.locals = args.iter().map(|(name, type_)| (*name, Some(type_.clone()))).collect();
+				checker.returns_value = return_type.is_some();
+				checker.check_block(body);
+				// the value at the end is what gets returned
+				if let (Some(value), true) = (&body.value, checker.returns_value) { checker.check_else(value) }
+			},
+			ConstAssignmentVal::Expression(expr) => {
+				checker.locals.clear();
+				checker.check_expression(expr);
+			},
+			ConstAssignmentVal::Struct(members) => {
+				checker.locals.clear();
+				for member in members.iter_mut() {
+					if let MemberDefault::Value(val) = &mut member.default { checker.check_expression(val); }
+				}
+			},
+			ConstAssignmentVal::Enum(_) | ConstAssignmentVal::Union(_) => (),
+		}
+	}
+
+	(checker.inferred, checker.errors)
+}
+
+#[derive(Default)]
+struct Checker {
+	/// As function pointer types
+	functions: HashMap<Symbol, Type>,
+	/// The members and whether they have a default, so they can be left out of a literal
+	structs: HashMap<Symbol, Vec<(Symbol, Type, bool)>>,
+	/// Of enums and unions, with the type of their data. An enum's variants don't have any
+	variants: HashMap<Symbol, Vec<(Symbol, Option<Type>)>>,
+	/// Constants and global variables
+	globals: HashMap<Symbol, Type>,
+	/// Arguments and lets in scope, innermost last. `None` if the type couldn't be inferred, that was already reported
+	locals: Vec<(Symbol, Option<Type>)>,
+	/// Whether the function being checked returns something
+	returns_value: bool,
+	inferred: Vec<Inferred>,
+	errors: Vec<TypeError>,
+}
+
+impl Checker {
+	/// The type of the value at the end, if there is one
+	fn check_block(&mut self, block: &mut Block) -> Option<Type> {
+		let scope = self.locals.len();
+		for statement in block.statements.iter_mut() {
+			self.check_statement(statement);
+		}
+
+		let type_ = block.value.as_mut().and_then(|value| self.check_expression(value));
+		self.locals.truncate(scope);
+		type_
+	}
+
+	fn check_statement(&mut self, statement: &mut Statement) {
+		match &mut statement.kind {
+			StatementKind::Let { name, name_span, type_, val, .. } => {
+				let errors = self.errors.len();
+				let val_type = val.as_mut().and_then(|val| self.check_value(val));
+				// without a value either the parser already complained
+				if type_.is_none() && val.is_some() {
+					// int x[4] can't be initialized with another array in C, so it's a pointer like everywhere else
+					match val_type.map(decay) {
+						Some(val_type) => {
+							self.inferred.push(Inferred { span: *name_span, type_: val_type.clone() });
+							*type_ = Some(val_type);
+						},
+						// the value had an error already, that's why
+						None if self.errors.len() > errors => (),
+						None => self.errors.push(TypeError { kind: TypeErrorKind::CantInfer(*name), span: *name_span }),
+					}
+				}
+
+				self.locals.push((*name, type_.clone()));
+			},
+			StatementKind::Return(expr) => {
+				self.check_expression(expr);
+				if self.returns_value { self.check_else(expr) }
+			},
+			StatementKind::Expression(expr) => { self.check_expression(expr); },
+			StatementKind::Break(_) | StatementKind::Continue(_) => (),
+		}
+	}
+
+	/// `None` when it doesn't have a value (or it couldn't be figured out)
+	fn check_expression(&mut self, expr: &mut Expression) -> Option<Type> {
+		if self.variant_of(expr).is_some() || matches!(&expr.kind, ExpressionKind::FunctionCall(callee, _) if self.variant_of(callee).is_some()) {
+			return self.check_variant(expr);
+		}
+
+		match &mut expr.kind {
+			ExpressionKind::NumberLiteral(n, suffix) => Some(named(integer_literal_type(*n, *suffix))),
+			ExpressionKind::FloatLiteral(_, suffix) => Some(named(match suffix {
+				None => "double",
+				Some(FloatSuffix::F) => "float",
+				Some(FloatSuffix::L) => "long double",
+			})),
+			ExpressionKind::StringLiteral(_) => Some(Type::Pointer(Box::new(named("char")))),
+			// 'a' is an int in C
+			ExpressionKind::CharLiteral(_) => Some(named("int")),
+			ExpressionKind::Ident(name) => self.lookup(*name),
+
+			ExpressionKind::BinaryOperator { op: Operator::MemberAccess, left, right } => {
+				let mut left_type = self.check_value(left)?;
+				let ExpressionKind::Ident(member) = right.kind else { return None };
+
+				// p.x with a pointer to a struct is (*p).x, the backend turns that into p->x
+				if let Type::Pointer(inner) = &left_type {
+					if matches!(**inner, Type::Named(name) if self.structs.contains_key(&name)) {
+						left_type = (**inner).clone();
+						let span = left.span;
+						let pointer = std::mem::replace(left.as_mut(), Expression { kind: ExpressionKind::NumberLiteral(0, None), span });
+						**left = Expression { kind: ExpressionKind::UnaryOperator { op: Operator::Deref, operand: Box::new(pointer) }, span };
+					}
+				}
+
+				let member_type = match &left_type {
+					Type::Named(name) if self.structs.contains_key(name) => {
+						self.structs[name].iter().find(|(name, ..)| *name == member).map(|(_, type_, _)| type_.clone())
+					},
+					// the struct the backend makes for it
+					Type::Slice(element) => match member.as_str() {
+						"ptr" => Some(Type::Pointer(element.clone())),
+						"len" => Some(named("size_t")),
+						_ => None,
+					},
+					_ => {
+						self.errors.push(TypeError { kind: TypeErrorKind::NoMembers(left_type), span: left.span });
+						return None;
+					},
+				};
+
+				if member_type.is_none() {
+					self.errors.push(TypeError { kind: TypeErrorKind::UnknownMember { type_: left_type, member }, span: right.span });
+				}
+
+				member_type
+			},
+			ExpressionKind::BinaryOperator { op, left, right } => {
+				let (left, right) = (self.check_value(left), self.check_value(right));
+				if let (Some(left), Some(right)) = (&left, &right) {
+					if !valid_pointer_arithmetic(op, left, right) {
+						self.errors.push(TypeError { kind: TypeErrorKind::InvalidPointerArithmetic(left.clone(), right.clone()), span: expr.span });
+						return None;
+					}
+				}
+
+				binary_operator_type(op, left?, right)
+			},
+			ExpressionKind::UnaryOperator { op, operand } => {
+				let operand_type = self.check_value(operand)?;
+				match op {
+					Operator::Deref => match decay(operand_type) {
+						Type::Pointer(inner) => Some(*inner),
+						// calling *f is the same as calling f
+						function @ Type::Function { .. } => Some(function),
+						type_ => {
+							self.errors.push(TypeError { kind: TypeErrorKind::NotAPointer(type_), span: operand.span });
+							None
+						},
+					},
+					Operator::AddressOf => Some(Type::Pointer(Box::new(operand_type))),
+					Operator::LogicalNot => Some(named("int")),
+					_ => promote(&operand_type).map(named),
+				}
+			},
+
+			ExpressionKind::FunctionCall(callee, args) => {
+				let callee_type = self.check_value(callee);
+				args.iter_mut().for_each(|arg| { self.check_value(arg); });
+
+				match callee_type? {
+					Type::Function { args: expected, .. } if expected.len() != args.len() => {
+						self.errors.push(TypeError { kind: TypeErrorKind::ArgumentCount { expected: expected.len(), found: args.len() }, span: expr.span });
+						None
+					},
+					Type::Function { return_type, .. } => return_type.map(|type_| *type_),
+					type_ => {
+						self.errors.push(TypeError { kind: TypeErrorKind::NotCallable(type_), span: callee.span });
+						None
+					},
+				}
+			},
+
+			ExpressionKind::StructLiteral { name, members } => {
+				members.iter_mut().for_each(|(_, val)| { self.check_value(val); });
+				let Some(fields) = self.structs.get(name).cloned() else {
+					self.errors.push(TypeError { kind: TypeErrorKind::UnknownStruct(*name), span: expr.span });
+					return None;
+				};
+
+				let type_ = Type::Named(*name);
+				// Point{1, 2} is Point{ x = 1, y = 2 }, the backend only deals with the named version
+				if members.first().is_some_and(|(member, _)| member.is_none()) {
+					if members.len() > fields.len() {
+						self.errors.push(TypeError { kind: TypeErrorKind::TooManyMembers { expected: fields.len(), found: members.len() }, span: expr.span });
+						return Some(type_);
+					}
+
+					members.iter_mut().zip(&fields).for_each(|((member, _), (field, ..))| *member = Some(*field));
+				}
+
+				for (i, (member, val)) in members.iter().enumerate() {
+					let Some(member) = *member else { continue };
+					if !fields.iter().any(|(field, ..)| *field == member) {
+						self.errors.push(TypeError { kind: TypeErrorKind::UnknownMember { type_: type_.clone(), member }, span: val.span });
+					} else if members[..i].iter().any(|(earlier, _)| *earlier == Some(member)) {
+						self.errors.push(TypeError { kind: TypeErrorKind::DuplicateMember(member), span: val.span });
+					}
+				}
+
+				// the ones with a default can be left out
+				let missing: Vec<_> = fields.iter()
+					.filter(|(_, _, default)| !default)
+					.map(|(field, ..)| *field)
+					.filter(|field| !members.iter().any(|(member, _)| *member == Some(*field)))
+					.collect();
+				if !missing.is_empty() {
+					self.errors.push(TypeError { kind: TypeErrorKind::MissingMembers { type_: type_.clone(), members: missing }, span: expr.span });
+				}
+
+				Some(type_)
+			},
+
+			// already turned into one
+			ExpressionKind::Variant { type_, data, .. } => {
+				if let Some(data) = data { self.check_value(data); }
+				Some(Type::Named(*type_))
+			},
+
+			ExpressionKind::Switch { value, arms, type_ } => {
+				let value_type = self.check_value(value);
+				let variants = match value_type {
+					Some(Type::Named(name)) if self.variants.contains_key(&name) => {
+						*type_ = Some(name);
+						Some((Type::Named(name), self.variants[&name].clone()))
+					},
+					Some(value_type) => {
+						self.errors.push(TypeError { kind: TypeErrorKind::NotSwitchable(value_type), span: value.span });
+						None
+					},
+					None => None,
+				};
+
+				let mut covered = Vec::new();
+				let mut switch_type = None;
+				for arm in arms.iter_mut() {
+					// Some(data) once it's known which variant the binding is for
+					let mut data = None;
+					if let Some((type_, variants)) = &variants {
+						for &(variant, span) in arm.variants.iter() {
+							match variants.iter().find(|(name, _)| *name == variant) {
+								None => self.errors.push(TypeError { kind: TypeErrorKind::UnknownVariant { type_: type_.clone(), variant }, span }),
+								Some(_) if covered.contains(&variant) => self.errors.push(TypeError { kind: TypeErrorKind::DuplicateArm(variant), span }),
+								Some((_, variant_data)) => {
+									covered.push(variant);
+									data = Some(variant_data.clone());
+								},
+							}
+						}
+					}
+
+					let scope = self.locals.len();
+					if let Some(binding) = arm.binding {
+						if let (Some(None), [(variant, span)]) = (&data, arm.variants.as_slice()) {
+							self.errors.push(TypeError { kind: TypeErrorKind::NoData(*variant), span: *span });
+						}
+
+						self.locals.push((binding, data.flatten()));
+					}
+
+					let body = self.check_expression(&mut arm.body);
+					self.locals.truncate(scope);
+
+					// like the branches of an if
+					switch_type = match (switch_type, body) {
+						(Some(switch_type), Some(body)) => usual_arithmetic_conversion(&switch_type, &body).map(named).or(Some(switch_type)),
+						(switch_type, body) => switch_type.or(body),
+					};
+				}
+
+				let has_else = arms.iter().any(|arm| arm.variants.is_empty());
+				if let (Some((type_, variants)), false) = (variants, has_else) {
+					let missing: Vec<_> = variants.into_iter().map(|(variant, _)| variant).filter(|variant| !covered.contains(variant)).collect();
+					if !missing.is_empty() {
+						self.errors.push(TypeError { kind: TypeErrorKind::NotExhaustive { type_, missing }, span: expr.span });
+					}
+				}
+
+				switch_type
+			},
+
+			ExpressionKind::If { cond, body, else_body } => {
+				self.check_value(cond);
+				let body = self.check_expression(body);
+				// without an else there's no value when the condition is false
+				let else_body = self.check_expression(else_body.as_mut()?);
+				match (body, else_body) {
+					(Some(body), Some(else_body)) => usual_arithmetic_conversion(&body, &else_body).map(named).or(Some(body)),
+					(body, else_body) => body.or(else_body),
+				}
+			},
+			ExpressionKind::Block(block) => self.check_block(block),
+			// check_value makes these after checking what's in them
+			ExpressionKind::Hoisted { type_, .. } => Some(type_.clone()),
+
+			// loops don't have a value, but there can be lets in them
+			ExpressionKind::While { cond, body, .. } => {
+				self.check_value(cond);
+				self.check_expression(body);
+				None
+			},
+			ExpressionKind::ForIn { var, start, end, body, type_: var_type, .. } => {
+				let (start, end) = (self.check_value(start), self.check_value(end));
+				// the type of start + end, like in C
+				let type_ = match (start, end) {
+					(Some(start), Some(end)) => {
+						let type_ = binary_operator_type(&Operator::Add, start.clone(), Some(end.clone()));
+						if type_.is_none() { self.errors.push(TypeError { kind: TypeErrorKind::InvalidRange(start, end), span: expr.span }) }
+						type_
+					},
+					_ => None,
+				};
+				var_type.clone_from(&type_);
+
+				self.locals.push((*var, type_));
+				self.check_expression(body);
+				self.locals.pop();
+				None
+			},
+			ExpressionKind::For { init, cond, step, body, .. } => {
+				let scope = self.locals.len();
+				if let Some(init) = init { self.check_statement(init) }
+				cond.iter_mut().chain(step).for_each(|expr| { self.check_value(expr); });
+				self.check_expression(body);
+				self.locals.truncate(scope);
+				None
+			},
+		}
+	}
+
+	/// An expression that's used for its value inside of another one. C doesn't have `if`, blocks and switches
+	/// as expressions, so they get wrapped in a `Hoisted` with their type for the backend
+	fn check_value(&mut self, expr: &mut Expression) -> Option<Type> {
+		let errors = self.errors.len();
+		let type_ = self.check_expression(expr);
+		if !expr.is_block_like() { return type_ }
+
+		self.check_else(expr);
+		let type_ = type_.map(decay);
+		match &type_ {
+			Some(type_) => {
+				let span = expr.span;
+				let value = std::mem::replace(expr, Expression { kind: ExpressionKind::NumberLiteral(0, None), span });
+				*expr = Expression { kind: ExpressionKind::Hoisted { value: Box::new(value), type_: type_.clone() }, span };
+			},
+			// it had an error already, that's why
+			None if self.errors.len() > errors => (),
+			None => self.errors.push(TypeError { kind: TypeErrorKind::NoValue, span: expr.span }),
+		}
+
+		type_
+	}
+
+	/// An expression whose value is used, an `if` in it without an `else` wouldn't always have one
+	fn check_else(&mut self, expr: &Expression) {
+		match &expr.kind {
+			ExpressionKind::If { else_body: None, .. } => self.errors.push(TypeError { kind: TypeErrorKind::MissingElse, span: expr.span }),
+			ExpressionKind::If { body, else_body: Some(else_body), .. } => {
+				self.check_else(body);
+				self.check_else(else_body);
+			},
+			ExpressionKind::Block(block) => if let Some(value) = &block.value { self.check_else(value) },
+			ExpressionKind::Switch { arms, .. } => arms.iter().for_each(|arm| self.check_else(&arm.body)),
+			_ => (),
+		}
+	}
+
+	/// The enum or union and the variant if `expr` is `Color.Red`, and `Color` isn't also the name of a variable
+	fn variant_of(&self, expr: &Expression) -> Option<(Symbol, Symbol)> {
+		let ExpressionKind::BinaryOperator { op: Operator::MemberAccess, left, right } = &expr.kind else { return None };
+		let (ExpressionKind::Ident(type_), ExpressionKind::Ident(variant)) = (&left.kind, &right.kind) else { return None };
+		if !self.variants.contains_key(type_) || self.lookup(*type_).is_some() { return None }
+
+		Some((*type_, *variant))
+	}
+
+	/// `Color.Red` or `Shape.Circle(2.0)`, which becomes an `ExpressionKind::Variant`
+	fn check_variant(&mut self, expr: &mut Expression) -> Option<Type> {
+		let (callee, args) = match std::mem::replace(&mut expr.kind, ExpressionKind::NumberLiteral(0, None)) {
+			ExpressionKind::FunctionCall(callee, args) => (*callee, Some(args)),
+			kind => (Expression { kind, span: expr.span }, None),
+		};
+
+		let (type_, variant) = self.variant_of(&callee).unwrap();
+		let error = match (self.variants[&type_].iter().find(|(name, _)| *name == variant), &args) {
+			(None, _) => Some(TypeErrorKind::UnknownVariant { type_: Type::Named(type_), variant }),
+			(Some((_, Some(_))), None) => Some(TypeErrorKind::MissingData(variant)),
+			(Some((_, Some(_))), Some(args)) if args.len() != 1 => Some(TypeErrorKind::ArgumentCount { expected: 1, found: args.len() }),
+			(Some((_, None)), Some(_)) => Some(TypeErrorKind::NoData(variant)),
+			_ => None,
+		};
+
+		let mut args = args.unwrap_or_default();
+		args.iter_mut().for_each(|arg| { self.check_value(arg); });
+		let data = if args.len() == 1 { args.pop().map(Box::new) } else { None };
+		expr.kind = ExpressionKind::Variant { type_, variant, data };
+
+		match error {
+			Some(kind) => {
+				self.errors.push(TypeError { kind, span: expr.span });
+				None
+			},
+			None => Some(Type::Named(type_)),
+		}
+	}
+
+	fn lookup(&self, name: Symbol) -> Option<Type> {
+		match self.locals.iter().rev().find(|(local, _)| *local == name) {
+			Some((_, type_)) => type_.clone(),
+			None => self.globals.get(&name).or_else(|| self.functions.get(&name)).cloned(),
+		}
+	}
+}
+
+pub(crate) fn named(name: &str) -> Type {
+	Type::Named(Symbol::intern(name))
+}
+
+/// Arrays are used as a pointer to their first element
+fn decay(type_: Type) -> Type {
+	match type_ {
+		Type::Array(inner, _) => Type::Pointer(inner),
+		type_ => type_,
+	}
+}
+
+/// The first of C's types for the literal that it fits in (assuming an lp64 target, where long is 64 bits).
+/// Decimals above i64::MAX are emitted in hex, so they get an unsigned type like hex literals do
+pub(crate) fn integer_literal_type(n: u64, suffix: Option<IntSuffix>) -> &'static str {
+	let fits_int = n <= i32::MAX as u64;
+	let fits_long = n <= i64::MAX as u64;
+	match suffix {
+		None if fits_int => "int",
+		None | Some(IntSuffix::L) if fits_long => "long",
+		None | Some(IntSuffix::L) => "unsigned long",
+		Some(IntSuffix::U) if n <= u32::MAX as u64 => "unsigned",
+		Some(IntSuffix::U) | Some(IntSuffix::UL) => "unsigned long",
+		Some(IntSuffix::LL) if fits_long => "long long",
+		Some(IntSuffix::LL) | Some(IntSuffix::ULL) => "unsigned long long",
+	}
+}
+
+fn binary_operator_type(op: &Operator, left: Type, right: Option<Type>) -> Option<Type> {
+	match op {
+		// the value of an assignment is whatever got assigned
+		Operator::Assign | Operator::AddAssign | Operator::SubtractAssign | Operator::MultiplyAssign | Operator::DivideAssign |
+		Operator::ModuloAssign | Operator::BitAndAssign | Operator::BitOrAssign | Operator::BitXorAssign |
+		Operator::ShiftLeftAssign | Operator::ShiftRightAssign => Some(left),
+
+		Operator::IsEqual | Operator::IsNotEqual | Operator::IsLessThan | Operator::IsGreaterThan |
+		Operator::IsLessOrEqual | Operator::IsGreaterOrEqual | Operator::LogicalAnd | Operator::LogicalOr => Some(named("int")),
+
+		Operator::ShiftLeft | Operator::ShiftRight => promote(&left).map(named),
+
+		// pointer arithmetic, p - q is a ptrdiff_t which is a long
+		Operator::Add | Operator::Subtract => match (decay(left), right.map(decay)?) {
+			(Type::Pointer(_), Type::Pointer(_)) if matches!(op, Operator::Subtract) => Some(named("long")),
+			(pointer @ Type::Pointer(_), _) => Some(pointer),
+			(_, pointer @ Type::Pointer(_)) if matches!(op, Operator::Add) => Some(pointer),
+			(left, right) => usual_arithmetic_conversion(&left, &right).map(named),
+		},
+
+		_ => usual_arithmetic_conversion(&left, &right?).map(named),
+	}
+}
+
+/// Pointers can only be added to integers, and subtracted from each other (when they're the same type)
+fn valid_pointer_arithmetic(op: &Operator, left: &Type, right: &Type) -> bool {
+	let (left, right) = (decay(left.clone()), decay(right.clone()));
+	let (left_pointer, right_pointer) = (matches!(left, Type::Pointer(_)), matches!(right, Type::Pointer(_)));
+	if !left_pointer && !right_pointer { return true }
+
+	match op {
+		Operator::Add => (left_pointer && integer(&right).is_some()) || (right_pointer && integer(&left).is_some()),
+		Operator::Subtract => (left_pointer && integer(&right).is_some()) || left == right,
+		Operator::AddAssign | Operator::SubtractAssign => left_pointer && integer(&right).is_some(),
+
+		Operator::Assign | Operator::IsEqual | Operator::IsNotEqual | Operator::IsLessThan | Operator::IsGreaterThan |
+		Operator::IsLessOrEqual | Operator::IsGreaterOrEqual | Operator::LogicalAnd | Operator::LogicalOr => true,
+		_ => false,
+	}
+}
+
+/// C's integer types with their rank, size (lp64) and whether they're signed
+const INTEGERS: &[(&str, u8, u8, bool)] = &[
+	("char", 1, 1, true),
+	("short", 2, 2, true),
+	("int", 3, 4, true),
+	("signed", 3, 4, true),
+	("unsigned", 3, 4, false),
+	("long", 4, 8, true),
+	("unsigned long", 4, 8, false),
+	("size_t", 4, 8, false),
+	("long long", 5, 8, true),
+	("unsigned long long", 5, 8, false),
+];
+
+/// From the lowest rank
+pub(crate) const FLOATS: &[&str] = &["float", "double", "long double"];
+
+pub(crate) fn integer(type_: &Type) -> Option<(&'static str, u8, u8, bool)> {
+	let Type::Named(name) = type_ else { return None };
+	INTEGERS.iter().find(|(integer, ..)| *integer == name.as_str()).copied()
+}
+
+pub(crate) fn float_rank(type_: &Type) -> Option<usize> {
+	let Type::Named(name) = type_ else { return None };
+	FLOATS.iter().position(|float| *float == name.as_str())
+}
+
+/// Anything smaller than an int becomes an int when it's used in arithmetic
+pub(crate) fn promote(type_: &Type) -> Option<&'static str> {
+	if let Some(rank) = float_rank(type_) { return Some(FLOATS[rank]) }
+
+	let (name, rank, ..) = integer(type_)?;
+	Some(if rank < 3 { "int" } else { name })
+}
+
+/// The common type of an arithmetic operator's operands
+pub(crate) fn usual_arithmetic_conversion(left: &Type, right: &Type) -> Option<&'static str> {
+	match (float_rank(left), float_rank(right)) {
+		(Some(left), Some(right)) => return Some(FLOATS[left.max(right)]),
+		(Some(float), None) if integer(right).is_some() => return Some(FLOATS[float]),
+		(None, Some(float)) if integer(left).is_some() => return Some(FLOATS[float]),
+		_ => (),
+	}
+
+	let (left, right) = (integer(&named(promote(left)?))?, integer(&named(promote(right)?))?);
+	if left.3 == right.3 { return Some(if left.1 >= right.1 { left.0 } else { right.0 }) }
+
+	// the signed one wins only if it can hold every value of the unsigned one
+	let (signed, unsigned) = if left.3 { (left, right) } else { (right, left) };
+	Some(if unsigned.1 >= signed.1 {
+		unsigned.0
+	} else if signed.2 > unsigned.2 {
+		signed.0
+	} else {
+		INTEGERS.iter().find(|(_, rank, _, is_signed)| *rank == signed.1 && !is_signed)?.0
+	})
+}
@@ -30,10 +30,28 @@ async function run_test(name: string) {
 	const compiler_out = await new Deno.Command("target/debug/loki.exe", {
 		args: [ test_file, "-o", out_exe_file ],
 		env: { "LOKI_RUNNING_TESTS": "yes" },
-		stderr: "inherit",
 	}).output();
+	const errors = decoder.decode(compiler_out.stderr);
+
+	// the values can have a = in them
+	const checks = decoder.decode(compiler_out.stdout).trim()
+		.split('\n')
+		.filter(l => l.startsWith("__t_"))
+		.map(l => [l.slice(0, l.indexOf('=')), l.slice(l.indexOf('=') + 1)]);
+
+	// the compiler is supposed to fail, there's nothing to run
+	const expected_error = checks.find(([check]) => check == "__t_expected_error");
+	if (expected_error) {
+		const message = JSON.parse(expected_error[1]);
+		if (compiler_out.code == 0 || !errors.includes(message))
+			die(`[${name}] Expected the error "${message}", got: ${errors}`);
+
+		console.log(name, "Test succesful");
+		return;
+	}
 
 	if (compiler_out.code != 0) {
+		console.error(errors);
 		die(`[${name}] The compiler emitted errors / panicked`);
 	}
 
@@ -41,11 +59,6 @@ async function run_test(name: string) {
 
 	const out = await new Deno.Command(out_exe_file).output();
 
-	const checks = decoder.decode(compiler_out.stdout).trim()
-		.split('\n')
-		.filter(l => l.startsWith("__t_"))
-		.map(l => l.split('='));
-
 	for (const [check, expected] of checks) {
 		if (check == "__t_expected_status") {
 			if (out.code != +expected)